@@ -14,6 +14,9 @@
 
 //! logging stuffs, inspired by databend
 use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
 use std::sync::{Arc, Mutex, Once};
 
 use once_cell::sync::Lazy;
@@ -35,8 +38,70 @@ pub use crate::{debug, error, info, log, trace, warn};
 static GLOBAL_LOG_GUARD: Lazy<Arc<Mutex<Option<Vec<WorkerGuard>>>>> =
     Lazy::new(|| Arc::new(Mutex::new(None)));
 
+/// The file log, shared so [`rotate_logs`] can swap it while the writer thread runs.
+static LOG_FILE: Lazy<Arc<Mutex<Option<LogFile>>>> = Lazy::new(|| Arc::new(Mutex::new(None)));
+
 const DEFAULT_LOG_TARGETS: &str = "info";
 
+struct LogFile {
+    app_name: String,
+    dir: String,
+    appender: Option<RollingFileAppender>,
+}
+
+impl LogFile {
+    fn open(app_name: &str, dir: &str) -> Result<RollingFileAppender, tracing_appender::rolling::InitError> {
+        RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(app_name)
+            .filename_suffix("log")
+            .max_log_files(5)
+            .build(dir)
+    }
+}
+
+/// Writes to the current [`LogFile`].
+struct SharedLogFile;
+
+impl Write for SharedLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG_FILE.lock().unwrap().as_mut().and_then(|f| f.appender.as_mut()) {
+            Some(appender) => appender.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG_FILE.lock().unwrap().as_mut().and_then(|f| f.appender.as_mut()) {
+            Some(appender) => appender.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Closes today's log file, renames it with the current time appended, and starts a new one.
+pub fn rotate_logs() -> io::Result<()> {
+    let mut guard = LOG_FILE.lock().unwrap();
+    let file = match guard.as_mut() {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+    // Windows cannot rename a file that is still open.
+    file.appender = None;
+
+    let now = chrono::Utc::now();
+    let dir = Path::new(&file.dir);
+    let current = dir.join(format!("{}.{}.log", file.app_name, now.format("%Y-%m-%d")));
+    let rotated = dir.join(format!("{}.{}.log", file.app_name, now.format("%Y-%m-%d-%H%M%S")));
+    let renamed = match fs::rename(&current, &rotated) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    };
+
+    file.appender = Some(LogFile::open(&file.app_name, &file.dir).map_err(io::Error::other)?);
+    renamed
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct LoggingOptions {
@@ -152,15 +217,15 @@ pub fn init_global_logging(
 
     // JSON log layer.
     let rolling_appender = 
-        RollingFileAppender::builder()
-            .rotation(Rotation::DAILY)
-            .filename_prefix(app_name)
-            .filename_suffix("log")
-            .max_log_files(5)
-            .build(dir)
+        LogFile::open(app_name, dir)
             .expect("initializing rolling file appender failed");
+    *LOG_FILE.lock().unwrap() = Some(LogFile {
+        app_name: app_name.to_owned(),
+        dir: dir.to_owned(),
+        appender: Some(rolling_appender),
+    });
     let (rolling_writer, rolling_writer_guard) = 
-        tracing_appender::non_blocking(rolling_appender);
+        tracing_appender::non_blocking(SharedLogFile);
     let file_logging_layer = 
         Layer::new().with_writer(rolling_writer).with_ansi(false);
     // let file_logging_layer = 
@@ -4,7 +4,7 @@ mod macros;
 mod panic_hook;
 
 
-pub use gol::{init_global_logging, init_default_logging};
+pub use gol::{init_global_logging, init_default_logging, rotate_logs};
 pub use panic_hook::set_panic_hook;
 
 
@@ -0,0 +1,356 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deploy::InstallDir,
+    pause::PauseUpdates,
+    registry::Hive,
+    scm::StartType,
+    update_policy::{DeferUpdates, DriverPolicy, UpdatePolicy},
+};
+
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+pub const DEFAULT_SCHEDULE: &str = "0 */5 * * * *";
+
+pub const BLOCK_WINDOWS_UPDATES: &[&str] =
+    &["wuauserv", "WaaSMedicSvc", "UsoSvc", "bits", "DoSvc", "PeerDistSvc", "appidsvc"];
+
+pub const BLOCK_SCHEDULED_TASKS: &[&str] =
+    &[r#"\Microsoft\Windows\WindowsUpdate\Scheduled Start"#];
+
+pub const BLOCK_PROCESSES: &[&str] = &["MoUsoCoreWorker", "TiWorker"];
+
+/// Services Windows Update needs to download and install anything, with their
+/// default start types. Outside [`Mode::Block`] they are set back to these.
+pub const UPDATE_SERVICES: &[(&str, StartType)] = &[
+    ("wuauserv", StartType::OnDemand),
+    ("UsoSvc", StartType::AutoStart),
+    ("bits", StartType::OnDemand),
+    ("DoSvc", StartType::AutoStart),
+];
+
+/// Scheduled tasks Windows Update needs to look for updates, enabled again
+/// outside [`Mode::Block`].
+pub const UPDATE_TASKS: &[&str] = &[r#"\Microsoft\Windows\WindowsUpdate\Scheduled Start"#];
+
+/// Settings driving the enforcement loop, loaded from a TOML file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Block, defer or pause Windows Update.
+    pub mode: Mode,
+    /// Cron expression (with seconds) for the enforcement job.
+    pub schedule: String,
+    /// System events that run an enforcement pass right away.
+    pub triggers: Vec<Trigger>,
+    /// Services to stop and disable.
+    pub services: Vec<String>,
+    /// Registry values to enforce.
+    pub registry: Vec<RegistryValue>,
+    /// Windows Update Group Policy settings, enforced as registry values.
+    pub update_policy: UpdatePolicy,
+    /// Driver update and installation restrictions, enforced as registry values.
+    pub drivers: DriverPolicy,
+    /// How long updates are deferred in [`Mode::Defer`].
+    pub defer_updates: DeferUpdates,
+    /// How long updates are paused in [`Mode::Pause`], the default pause when
+    /// not set. Rejected in the other modes.
+    pub pause_updates: Option<PauseUpdates>,
+    /// Scheduled tasks to disable, by full task path.
+    pub tasks: Vec<String>,
+    /// Process names (without `.exe`) to kill.
+    pub processes: Vec<String>,
+    /// Who may control the blocker's own service; its permissions are left
+    /// alone when not set.
+    pub service_permissions: Option<ServicePermissions>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Block,
+            schedule: DEFAULT_SCHEDULE.to_owned(),
+            triggers: vec![Trigger::Resume, Trigger::Logon, Trigger::Preshutdown],
+            services: BLOCK_WINDOWS_UPDATES.iter().map(|x| x.to_string()).collect(),
+            registry: vec![
+                RegistryValue {
+                    key: r#"HKLM\Software\Microsoft\WindowsUpdate\UX\Settings"#.to_owned(),
+                    name: "UxOption".to_owned(),
+                    value: RegistryData::Dword(1),
+                },
+                RegistryValue {
+                    key: r#"HKLM\SYSTEM\ControlSet001\Services\WaaSMedicSvc"#.to_owned(),
+                    name: "Start".to_owned(),
+                    value: RegistryData::Dword(4),
+                },
+            ],
+            update_policy: UpdatePolicy::default(),
+            drivers: DriverPolicy::default(),
+            defer_updates: DeferUpdates::default(),
+            pause_updates: None,
+            tasks: BLOCK_SCHEDULED_TASKS.iter().map(|x| x.to_string()).collect(),
+            processes: BLOCK_PROCESSES.iter().map(|x| x.to_string()).collect(),
+            service_permissions: None,
+        }
+    }
+}
+
+/// What the blocker does about Windows Update.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Disable the update services and kill the update processes.
+    #[default]
+    Block,
+    /// Let updates in late, see [`Config::defer_updates`].
+    Defer,
+    /// Keep updates paused, see [`Config::pause_updates`].
+    Pause,
+}
+
+impl Mode {
+    /// Whether Windows Update must keep working: the [`UPDATE_SERVICES`] and
+    /// [`UPDATE_TASKS`] are kept at their defaults and no update process is
+    /// killed, or the deferral or pause would never end in an update.
+    pub fn keeps_updates_running(self) -> bool {
+        self != Mode::Block
+    }
+}
+
+/// A system event the service reacts to with an immediate enforcement pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trigger {
+    /// Resume from sleep or hibernation.
+    Resume,
+    /// A user logs on.
+    Logon,
+    /// A user unlocks the workstation.
+    Unlock,
+    /// The system is about to shut down; the pass runs before the service stops.
+    Preshutdown,
+}
+
+/// Permissions on the blocker's own service, applied at install and on every cycle.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServicePermissions {
+    /// Group or user (e.g. `Lab Operators` or `DOMAIN\Helpdesk`) allowed to
+    /// start, stop and pause the service.
+    pub operators: Option<String>,
+    /// Only SYSTEM and Administrators may stop or delete the service. Any other
+    /// permission is removed, and `operators` may start and pause it but not stop it.
+    /// Without `operators` this is the DACL Windows gives every new service, so
+    /// all it does is remove permissions added since.
+    pub harden: bool,
+}
+
+/// A single registry value, e.g.
+/// `{ key = 'HKLM\Software\Microsoft\WindowsUpdate\UX\Settings', name = "UxOption", value = 1 }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegistryValue {
+    pub key: String,
+    pub name: String,
+    pub value: RegistryData,
+}
+
+/// Registry value data, `REG_DWORD` for integers and `REG_SZ` for strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "a registry value: an integer from 0 to 4294967295 (REG_DWORD) or a string (REG_SZ)"
+)]
+pub enum RegistryData {
+    Dword(u32),
+    String(String),
+}
+
+impl RegistryValue {
+    /// Splits `key` into its root hive and the sub key below it.
+    pub fn split_key(&self) -> Option<(Hive, &str)> {
+        Hive::split_path(&self.key)
+    }
+}
+
+impl Config {
+    /// Default config location: the install directory, see [`InstallDir::local`].
+    pub fn default_path() -> PathBuf {
+        InstallDir::local().config()
+    }
+
+    /// Loads the config from `path`, or from [`Config::default_path`] when `path` is `None`.
+    ///
+    /// A missing file at the default location falls back to the built-in defaults,
+    /// a missing explicit file is an error.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, explicit) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => (Self::default_path(), false),
+        };
+
+        if !explicit && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::from_toml(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: Config = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        cron::Schedule::from_str(&self.schedule)
+            .map_err(|e| anyhow!("schedule {:?} is not a valid cron expression: {}", self.schedule, e))?;
+
+        if let Some(name) = self.services.iter().find(|x| x.trim().is_empty()) {
+            return Err(anyhow!("services: invalid service name {:?}", name));
+        }
+        if let Some(name) = self.tasks.iter().find(|x| !x.starts_with('\\')) {
+            return Err(anyhow!("tasks: {:?} must be a full task path starting with '\\'", name));
+        }
+        if let Some(name) = self
+            .processes
+            .iter()
+            .find(|x| x.trim().is_empty() || x.contains(['\\', '/']))
+        {
+            return Err(anyhow!("processes: invalid process name {:?}", name));
+        }
+        if let Some(operators) = self
+            .service_permissions
+            .as_ref()
+            .and_then(|p| p.operators.as_ref())
+            .filter(|x| x.trim().is_empty())
+        {
+            return Err(anyhow!("service_permissions: invalid operators {:?}", operators));
+        }
+        self.update_policy.validate()?;
+        self.drivers.validate()?;
+        self.defer_updates.validate()?;
+        if let Some(pause) = &self.pause_updates {
+            if self.mode != Mode::Pause {
+                return Err(anyhow!(
+                    "pause_updates is only used with mode = \"pause\", set the mode or remove pause_updates"
+                ));
+            }
+            pause.validate()?;
+        }
+        for value in &self.registry {
+            if value.split_key().is_none() {
+                return Err(anyhow!(
+                    "registry: key {:?} must start with HKLM\\ or HKCU\\",
+                    value.key
+                ));
+            }
+            if value.name.is_empty() {
+                return Err(anyhow!("registry: value name under {:?} is empty", value.key));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        format!("{:#}", Config::from_toml(text).unwrap_err())
+    }
+
+    #[test]
+    fn example_config_matches_the_defaults() {
+        let text = include_str!("../config.example.toml");
+        assert_eq!(Config::from_toml(text).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn rejects_an_invalid_cron_expression() {
+        let err = error(r#"schedule = "every five minutes""#);
+        assert!(
+            err.starts_with(r#"schedule "every five minutes" is not a valid cron expression"#),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let err = error("servces = []");
+        assert!(err.contains("unknown field `servces`"), "{}", err);
+
+        let err = error(
+            r#"
+            [[registry]]
+            key = 'HKLM\Software\Test'
+            name = "Value"
+            value = 1
+            type = "dword"
+            "#,
+        );
+        assert!(err.contains("unknown field `type`"), "{}", err);
+
+        let err = error(
+            r#"
+            [service_permissions]
+            harden = true
+            operator = "Lab Operators"
+            "#,
+        );
+        assert!(err.contains("unknown field `operator`"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_a_bad_registry_root() {
+        for key in [r#"HKEY_USERS\Test"#, r#"Software\Test"#, "HKLM", r#"HKLM\"#] {
+            let err = error(&format!(
+                "[[registry]]\nkey = '{}'\nname = \"Value\"\nvalue = 1\n",
+                key
+            ));
+            assert!(
+                err.contains("must start with HKLM\\ or HKCU\\"),
+                "{}: {}",
+                key,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn explains_bad_registry_data() {
+        for value in ["-1", "4294967296", "1.5", "true", "[1]"] {
+            let err = error(&format!(
+                "[[registry]]\nkey = 'HKLM\\Software\\Test'\nname = \"Value\"\nvalue = {}\n",
+                value
+            ));
+            assert!(
+                err.contains(
+                    "a registry value: an integer from 0 to 4294967295 (REG_DWORD) or a string (REG_SZ)"
+                ),
+                "{}: {}",
+                value,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_empty_names() {
+        assert!(error(r#"services = [" "]"#).contains("invalid service name"));
+        assert!(error(r#"tasks = ["Microsoft\\Windows"]"#).contains("must be a full task path"));
+        assert!(error(r#"processes = ["C:\\TiWorker"]"#).contains("invalid process name"));
+    }
+}
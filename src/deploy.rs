@@ -0,0 +1,265 @@
+//! The managed directory the service runs from, `%ProgramFiles%\WindowsUpdateBlocker`.
+//!
+//! `install` copies the binary and config there, so the service does not break
+//! when the downloaded installer is deleted. `upgrade` swaps the binary in
+//! place and keeps the previous one until the new one is known to start.
+//!
+//! The config, snapshot and tamper log are always read from there, so every
+//! command sees the service's files whichever binary it is run from.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use own_logger::*;
+
+use crate::{config::CONFIG_FILE_NAME, snapshot::SNAPSHOT_FILE_NAME, tamper::TAMPER_LOG_FILE_NAME};
+
+pub const INSTALL_DIR_NAME: &str = "WindowsUpdateBlocker";
+
+/// File name of the installed binary, whatever the installer was called.
+pub const EXE_NAME: &str = concat!(env!("CARGO_PKG_NAME"), ".exe");
+
+/// An install directory and the files the service uses in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallDir {
+    dir: PathBuf,
+}
+
+impl InstallDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `%ProgramFiles%\WindowsUpdateBlocker`.
+    pub fn managed() -> Self {
+        let program_files = env::var_os("ProgramFiles")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\Program Files"));
+        Self::new(program_files.join(INSTALL_DIR_NAME))
+    }
+
+    /// Where the blocker keeps its files on this computer: the managed
+    /// directory on Windows, the directory of the running executable elsewhere.
+    pub fn local() -> Self {
+        if cfg!(windows) {
+            Self::managed()
+        } else {
+            Self::current()
+        }
+    }
+
+    /// The directory of the running executable.
+    pub fn current() -> Self {
+        Self::new(
+            env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+                .unwrap_or_default(),
+        )
+    }
+
+    /// The managed directory of `computer`, assuming the default `C:\Program Files`:
+    /// the path its service manager runs the binary from, and the same directory
+    /// reached from here through the `C$` administrative share.
+    pub fn remote(computer: &str) -> (Self, Self) {
+        let local = Path::new(r"C:\Program Files").join(INSTALL_DIR_NAME);
+        let share = PathBuf::from(format!(r"\\{}\C$\Program Files", computer)).join(INSTALL_DIR_NAME);
+        (Self::new(local), Self::new(share))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn exe(&self) -> PathBuf {
+        self.dir.join(EXE_NAME)
+    }
+
+    /// The config the installed service loads by default, see [`crate::Config::default_path`].
+    pub fn config(&self) -> PathBuf {
+        self.dir.join(CONFIG_FILE_NAME)
+    }
+
+    /// The original system state, see [`crate::snapshot::Snapshot::default_path`].
+    pub fn snapshot(&self) -> PathBuf {
+        self.dir.join(SNAPSHOT_FILE_NAME)
+    }
+
+    /// The tamper events, see [`crate::tamper::TamperLog::default_path`].
+    pub fn tamper_log(&self) -> PathBuf {
+        self.dir.join(TAMPER_LOG_FILE_NAME)
+    }
+
+    /// Whether `path` is the installed binary.
+    pub fn is_installed_exe(&self, path: &Path) -> bool {
+        same_file(path, &self.exe())
+    }
+
+    /// Copies `exe` and, if given, `config` into the directory, creating it as needed.
+    pub fn deploy(&self, exe: &Path, config: Option<&Path>) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        copy_unless_same(exe, &self.exe())?;
+        if let Some(config) = config {
+            copy_unless_same(config, &self.config())?;
+        }
+        info!("deployed to {}", self.dir.display());
+        Ok(())
+    }
+
+    /// Replaces the installed binary with `exe`, keeping the previous one
+    /// until the swap is committed or rolled back.
+    ///
+    /// The new binary is staged next to the old one and renamed over it, so the
+    /// installed path always holds a complete binary.
+    pub fn swap_binary(&self, exe: &Path) -> Result<BinarySwap> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let target = self.exe();
+        let staged = target.with_extension("exe.new");
+        let backup = target.with_extension("exe.old");
+
+        fs::copy(exe, &staged)
+            .with_context(|| format!("Failed to copy {} to {}", exe.display(), staged.display()))?;
+        let backup = if target.exists() {
+            fs::copy(&target, &backup).with_context(|| {
+                format!("Failed to back up {} to {}", target.display(), backup.display())
+            })?;
+            Some(backup)
+        } else {
+            None
+        };
+        if let Err(e) = fs::rename(&staged, &target) {
+            let _ = fs::remove_file(&staged);
+            if let Some(backup) = &backup {
+                let _ = fs::remove_file(backup);
+            }
+            return Err(e).with_context(|| format!("Failed to replace {}", target.display()));
+        }
+        info!("replaced {}", target.display());
+        Ok(BinarySwap { target, backup })
+    }
+}
+
+/// A replaced binary whose predecessor is kept for a rollback.
+#[must_use = "commit or roll back the swap"]
+#[derive(Debug)]
+pub struct BinarySwap {
+    target: PathBuf,
+    /// `None` when there was no binary to replace.
+    backup: Option<PathBuf>,
+}
+
+impl BinarySwap {
+    /// Keeps the new binary and deletes the previous one.
+    pub fn commit(self) {
+        if let Some(backup) = &self.backup {
+            if let Err(e) = fs::remove_file(backup) {
+                warn!("failed to remove {}: {}", backup.display(), e);
+            }
+        }
+    }
+
+    /// Puts the previous binary back, or removes the new one if there was none.
+    pub fn rollback(self) -> Result<()> {
+        match &self.backup {
+            Some(backup) => fs::rename(backup, &self.target).with_context(|| {
+                format!("Failed to restore {} from {}", self.target.display(), backup.display())
+            })?,
+            None => fs::remove_file(&self.target)
+                .with_context(|| format!("Failed to remove {}", self.target.display()))?,
+        }
+        info!("rolled back {}", self.target.display());
+        Ok(())
+    }
+}
+
+fn copy_unless_same(from: &Path, to: &Path) -> Result<()> {
+    if same_file(from, to) {
+        return Ok(());
+    }
+    fs::copy(from, to)
+        .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+    Ok(())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(installed: Option<&[u8]>) -> (tempfile::TempDir, InstallDir, PathBuf) {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = InstallDir::new(tmp.path().join(INSTALL_DIR_NAME));
+        if let Some(bytes) = installed {
+            fs::create_dir_all(dir.dir()).unwrap();
+            fs::write(dir.exe(), bytes).unwrap();
+        }
+        let new = tmp.path().join("installer.exe");
+        fs::write(&new, b"new").unwrap();
+        (tmp, dir, new)
+    }
+
+    fn files(dir: &InstallDir) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir.dir())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn commit_keeps_the_new_binary_and_removes_the_backup() {
+        let (_tmp, dir, new) = setup(Some(b"old"));
+        let swap = dir.swap_binary(&new).unwrap();
+        assert_eq!(fs::read(dir.exe()).unwrap(), b"new");
+        assert_eq!(
+            files(&dir),
+            [EXE_NAME.to_owned(), format!("{}.old", EXE_NAME)]
+        );
+
+        swap.commit();
+        assert_eq!(fs::read(dir.exe()).unwrap(), b"new");
+        assert_eq!(files(&dir), [EXE_NAME]);
+    }
+
+    #[test]
+    fn rollback_restores_the_old_binary() {
+        let (_tmp, dir, new) = setup(Some(b"old"));
+        dir.swap_binary(&new).unwrap().rollback().unwrap();
+        assert_eq!(fs::read(dir.exe()).unwrap(), b"old");
+        assert_eq!(files(&dir), [EXE_NAME]);
+    }
+
+    #[test]
+    fn swap_onto_a_missing_target() {
+        let (_tmp, dir, new) = setup(None);
+        let swap = dir.swap_binary(&new).unwrap();
+        assert_eq!(fs::read(dir.exe()).unwrap(), b"new");
+        assert_eq!(files(&dir), [EXE_NAME]);
+        // Nothing to go back to: the rollback removes the new binary.
+        swap.rollback().unwrap();
+        assert_eq!(files(&dir), Vec::<String>::new());
+
+        dir.swap_binary(&new).unwrap().commit();
+        assert_eq!(fs::read(dir.exe()).unwrap(), b"new");
+    }
+
+    #[test]
+    fn a_failed_swap_leaves_the_installed_binary_alone() {
+        let (tmp, dir, _) = setup(Some(b"old"));
+        assert!(dir.swap_binary(&tmp.path().join("missing.exe")).is_err());
+        assert_eq!(fs::read(dir.exe()).unwrap(), b"old");
+        assert_eq!(files(&dir), [EXE_NAME]);
+    }
+}
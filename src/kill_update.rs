@@ -1,57 +1,275 @@
 use std::{
     ffi::OsString,
-    path::PathBuf,
-    time::{Duration, Instant},
+    fmt,
+    path::{Path, PathBuf},
 };
 
+use anyhow::anyhow;
 use own_logger::*;
 use splitty::split_unquoted_whitespace;
-use windows_service::service::{ServiceInfo, ServiceStartType};
-
-use crate::{serv_change_config, serv_get_config, serv_stop}; 
-
-pub const BLOCK_WINDOWS_UPDATES: &[&'static str] =
-    &["wuauserv", "WaaSMedicSvc", "UsoSvc", "bits", "DoSvc", "PeerDistSvc", "appidsvc"];
-
-pub fn kill() -> anyhow::Result<()> {
-    BLOCK_WINDOWS_UPDATES.iter().for_each(|name| {
-        info!("service::kill_update_service: killing {}", name);
-        // std::process::Command::new("taskkill").args(&["/IM", name]).spawn().ok();
-        let _ = serv_stop(name);
-        let serv_config = serv_get_config(name);
-        match serv_config {
-            Ok(config) => {
-                if config.start_type != ServiceStartType::Disabled {
-                    let path = config.executable_path.to_str().unwrap();
-
-                    let split_path: Vec<&str> = split_unquoted_whitespace(path)
-                        .unwrap_quotes(true)
-                        .collect();
-                    
-                    let buffer: Vec<OsString> =
-                        split_path.iter().map(|x| OsString::from(x)).collect();
-
-                    let new_config = ServiceInfo {
-                        name: OsString::from(name),
-                        display_name: config.display_name,
-                        service_type: config.service_type,
-                        start_type: ServiceStartType::Disabled,
-                        error_control: config.error_control,
-                        executable_path: PathBuf::from(buffer[0].clone()),
-                        launch_arguments: buffer[1..].to_vec(),
-                        dependencies: config.dependencies,
-                        account_name: config.account_name,
-                        account_password: None,
-                    };
-
-                    let _ = serv_change_config(name, new_config);
-                } else {
-                    info!("{} is disabled", name);
-                }
-            }
-            Err(_) => {}
+
+use crate::scm::{
+    is_not_found, ServiceConfig, ServiceController, ServiceError, ServiceOptions, ServiceSpec,
+    ServiceState, StartType,
+};
+use crate::{serv_change_config, serv_get_config, serv_stop};
+
+/// What [`kill_service`] did with one service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KillOutcome {
+    AlreadyDisabled,
+    Disabled,
+    NotPresent,
+    Failed(String),
+}
+
+impl fmt::Display for KillOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KillOutcome::AlreadyDisabled => write!(f, "already disabled"),
+            KillOutcome::Disabled => write!(f, "disabled now"),
+            KillOutcome::NotPresent => write!(f, "not present"),
+            KillOutcome::Failed(e) => write!(f, "failed: {}", e),
         }
-    });
+    }
+}
+
+/// Stops and disables `name` unless it already is, then checks that it stayed that way.
+pub fn kill_service(ctl: &dyn ServiceController, name: &str) -> KillOutcome {
+    let query = || -> Result<(StartType, ServiceState), std::io::Error> {
+        let config = ctl.query_config(name)?;
+        let status = ctl.query_status(name)?;
+        Ok((config.start_type, status.current_state))
+    };
+    match query() {
+        Ok((StartType::Disabled, ServiceState::Stopped)) => return KillOutcome::AlreadyDisabled,
+        Ok(_) => {}
+        Err(e) if is_not_found(&e) => return KillOutcome::NotPresent,
+        Err(e) => return KillOutcome::Failed(ServiceError::from(e).to_string()),
+    }
+    if let Err(e) = disable(ctl, name) {
+        return KillOutcome::Failed(format!("{:#}", e));
+    }
+    match query() {
+        Ok((StartType::Disabled, ServiceState::Stopped)) => KillOutcome::Disabled,
+        Ok((start_type, state)) => KillOutcome::Failed(format!("still {:?}/{:?}", start_type, state)),
+        Err(e) => KillOutcome::Failed(ServiceError::from(e).to_string()),
+    }
+}
 
+/// Stops `name` and sets its start type to Disabled.
+///
+/// A failed stop is only logged: once the service is disabled it cannot come
+/// back, and the caller checks its state afterwards.
+pub fn disable(ctl: &dyn ServiceController, name: &str) -> anyhow::Result<()> {
+    if let Err(e) = serv_stop(ctl, name) {
+        warn!("{}: stop failed: {}", name, e);
+    }
+    set_start_type(ctl, name, StartType::Disabled)
+}
+
+/// Changes only the start type of `name`, keeping the rest of its configuration.
+///
+/// Unlike [`reconfigure`] the service is not stopped first: a running service
+/// keeps running, the new start type applies from its next start.
+pub fn set_start_type(
+    ctl: &dyn ServiceController,
+    name: &str,
+    start_type: StartType,
+) -> anyhow::Result<()> {
+    let config = serv_get_config(ctl, name)?;
+    if config.start_type == start_type {
+        info!("{} is {:?}", name, start_type);
+        return Ok(());
+    }
+    let image_path = config.executable_path.clone();
+    let spec = service_spec(name, config, start_type, &image_path)?;
+    ctl.change_config(name, &spec).map_err(ServiceError::from)?;
+    info!("{} is now {:?}", name, start_type);
     Ok(())
 }
+
+/// Changes the start type of `name` and, when given, its full command line (`ImagePath`).
+///
+/// A running service is stopped first, see [`serv_change_config`].
+pub fn reconfigure(
+    ctl: &dyn ServiceController,
+    name: &str,
+    start_type: StartType,
+    image_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    let config = serv_get_config(ctl, name)?;
+    let image_path = image_path.map_or_else(|| config.executable_path.clone(), Path::to_path_buf);
+    if config.start_type == start_type && config.executable_path == image_path {
+        info!("{} is {:?}", name, start_type);
+        return Ok(());
+    }
+    let spec = service_spec(name, config, start_type, &image_path)?;
+    Ok(serv_change_config(ctl, name, spec)?)
+}
+
+/// `config` of `name` with `start_type` and the command line `image_path`,
+/// split into the executable and its arguments.
+fn service_spec(
+    name: &str,
+    config: ServiceConfig,
+    start_type: StartType,
+    image_path: &Path,
+) -> anyhow::Result<ServiceSpec> {
+    let path = image_path
+        .to_str()
+        .ok_or_else(|| anyhow!("{}: ImagePath {:?} is not valid unicode", name, image_path))?;
+
+    let split_path: Vec<&str> = split_unquoted_whitespace(path)
+        .unwrap_quotes(true)
+        .collect();
+
+    let buffer: Vec<OsString> =
+        split_path.iter().map(OsString::from).collect();
+    if buffer.is_empty() {
+        return Err(anyhow!("{}: ImagePath is empty", name));
+    }
+
+    Ok(ServiceSpec {
+        name: OsString::from(name),
+        display_name: config.display_name,
+        service_type: config.service_type,
+        start_type,
+        error_control: config.error_control,
+        executable_path: PathBuf::from(buffer[0].clone()),
+        launch_arguments: buffer[1..].to_vec(),
+        dependencies: config.dependencies,
+        account_name: config.account_name,
+        account_password: None,
+        options: ServiceOptions::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::*;
+    use crate::scm::{
+        FakeOp, FakeServiceController, ERROR_ACCESS_DENIED, SERVICE_ERROR_NORMAL,
+        SERVICE_WIN32_OWN_PROCESS,
+    };
+
+    const NAME: &str = "wuauserv";
+
+    fn running() -> FakeServiceController {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::OnDemand, ServiceState::Running);
+        ctl
+    }
+
+    #[test]
+    fn disables_a_running_service() {
+        let ctl = running();
+        assert_eq!(kill_service(&ctl, NAME), KillOutcome::Disabled);
+        assert_eq!(ctl.start_type(NAME), Some(StartType::Disabled));
+        assert_eq!(ctl.state(NAME), Some(ServiceState::Stopped));
+    }
+
+    #[test]
+    fn leaves_a_disabled_service_alone() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::Disabled, ServiceState::Stopped);
+        // Any change would fail.
+        ctl.fail_next(FakeOp::ChangeConfig, NAME, ERROR_ACCESS_DENIED);
+        assert_eq!(kill_service(&ctl, NAME), KillOutcome::AlreadyDisabled);
+    }
+
+    #[test]
+    fn reports_a_missing_service() {
+        let ctl = FakeServiceController::new();
+        assert_eq!(kill_service(&ctl, NAME), KillOutcome::NotPresent);
+    }
+
+    #[test]
+    fn reports_a_failed_query() {
+        let ctl = running();
+        ctl.fail_next(FakeOp::QueryConfig, NAME, ERROR_ACCESS_DENIED);
+        assert!(matches!(kill_service(&ctl, NAME), KillOutcome::Failed(_)));
+        assert_eq!(ctl.start_type(NAME), Some(StartType::OnDemand));
+    }
+
+    #[test]
+    fn reports_a_failed_reconfigure() {
+        let ctl = running();
+        ctl.fail_next(FakeOp::ChangeConfig, NAME, ERROR_ACCESS_DENIED);
+        assert!(matches!(kill_service(&ctl, NAME), KillOutcome::Failed(_)));
+        assert_eq!(ctl.start_type(NAME), Some(StartType::OnDemand));
+    }
+
+    #[test]
+    fn reports_a_service_that_keeps_running() {
+        let ctl = running();
+        ctl.fail_next(FakeOp::Stop, NAME, ERROR_ACCESS_DENIED);
+        assert_eq!(
+            kill_service(&ctl, NAME),
+            KillOutcome::Failed("still Disabled/Running".to_owned())
+        );
+    }
+
+    #[test]
+    fn set_start_type_leaves_a_running_service_running() {
+        let ctl = running();
+        set_start_type(&ctl, NAME, StartType::AutoStart).unwrap();
+        assert_eq!(ctl.start_type(NAME), Some(StartType::AutoStart));
+        assert_eq!(ctl.state(NAME), Some(ServiceState::Running));
+        let config = ctl.query_config(NAME).unwrap();
+        assert_eq!(
+            config.executable_path,
+            PathBuf::from(r"C:\Windows\system32\svchost.exe -k wuauserv")
+        );
+
+        // Nothing to change, nothing written.
+        ctl.fail_next(FakeOp::ChangeConfig, NAME, ERROR_ACCESS_DENIED);
+        set_start_type(&ctl, NAME, StartType::AutoStart).unwrap();
+    }
+
+    #[test]
+    fn reconfigure_stops_a_running_service() {
+        let ctl = running();
+        reconfigure(&ctl, NAME, StartType::AutoStart, None).unwrap();
+        assert_eq!(ctl.start_type(NAME), Some(StartType::AutoStart));
+        assert_eq!(ctl.state(NAME), Some(ServiceState::Stopped));
+    }
+
+    #[test]
+    fn reports_an_empty_image_path() {
+        let ctl = FakeServiceController::new();
+        ctl.create(&ServiceSpec {
+            name: OsString::from(NAME),
+            display_name: OsString::from(NAME),
+            service_type: SERVICE_WIN32_OWN_PROCESS,
+            start_type: StartType::AutoStart,
+            error_control: SERVICE_ERROR_NORMAL,
+            executable_path: PathBuf::new(),
+            launch_arguments: vec![],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+            options: ServiceOptions::default(),
+        })
+        .unwrap();
+        match kill_service(&ctl, NAME) {
+            KillOutcome::Failed(e) => assert!(e.contains("ImagePath is empty"), "{}", e),
+            outcome => panic!("{:?}", outcome),
+        }
+        assert_eq!(ctl.start_type(NAME), Some(StartType::AutoStart));
+    }
+
+    #[test]
+    fn reconfigure_keeps_the_command_line() {
+        let ctl = running();
+        reconfigure(&ctl, NAME, StartType::Disabled, None).unwrap();
+        let config = ctl.query_config(NAME).unwrap();
+        assert_eq!(config.start_type, StartType::Disabled);
+        assert_eq!(
+            config.executable_path,
+            PathBuf::from(r"C:\Windows\system32\svchost.exe -k wuauserv")
+        );
+    }
+}
@@ -1,18 +1,39 @@
+pub mod deploy;
 pub mod os;
+pub mod pause;
+pub mod policy;
+pub mod process;
+pub mod registry;
+pub mod rules;
+pub mod scm;
+pub mod snapshot;
+pub mod tamper;
+pub mod tasks;
+pub mod update_policy;
+mod config;
 mod logging;
 mod service;
 mod kill_update;
 
+pub use config::{
+    Config, Mode, RegistryData, RegistryValue, ServicePermissions, Trigger,
+    BLOCK_WINDOWS_UPDATES, UPDATE_SERVICES, UPDATE_TASKS,
+};
 pub use logging::Logging;
+#[cfg(windows)]
+pub use service::{SERVICE_TYPE, ServiceStatusEx, StatusReporter};
 pub use service::{
-    SERVICE_TYPE, 
-    ServiceStatusEx,
-    WindowsService,
     install as serv_install,
     uninstall as serv_uninstall,
     start as serv_start,
     stop as serv_stop,
     get_config as serv_get_config,
     change_config as serv_change_config,
+    notify as serv_notify,
+    wait_for_state as serv_wait_for_state,
+    wait_for_progress as serv_wait_for_progress,
+    UserEvent,
+    SERVICE_NAME,
+    STATE_TIMEOUT,
 };
-pub use kill_update::kill;
\ No newline at end of file
+pub use kill_update::reconfigure;
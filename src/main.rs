@@ -1,29 +1,74 @@
+use anyhow::{anyhow, Context};
 use clap::Parser;
-use tokio::process::Command;
-use tokio_cron_scheduler::{JobScheduler, Job};
-use std::{ffi::OsString, env};
-use anyhow::anyhow;
 use own_logger::*;
-use tokio_util::sync::CancellationToken;
-use windows_service::{
-    define_windows_service,
-    service::{
-        ServiceControl, ServiceErrorControl, 
-        ServiceInfo, ServiceStartType,  ServiceStatus, 
+use std::{
+    env,
+    ffi::OsString,
+    fmt,
+    future::Future,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
     },
-    service_control_handler::{self, ServiceControlHandlerResult},
-    service_dispatcher,
+    time::Duration,
 };
-use window_update_blocker::{os::windows::is_elevated, 
-    serv_install, serv_uninstall, serv_start, serv_stop,
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tokio_util::sync::CancellationToken;
+use window_update_blocker::{
+    policy::Policy,
+    rules::{Compliance, Host},
+    scm::{FailureActions, ServiceError, ServiceOptions, SidType},
+    snapshot::Snapshot,
+    tamper::TamperLog,
+    Config, Logging, Trigger, UserEvent,
+};
+#[cfg(windows)]
+use {
+    once_cell::sync::OnceCell,
+    window_update_blocker::{
+        deploy::InstallDir,
+        policy::RuleStatus,
+        rules::{Rule, ServiceDacl},
+        update_policy::running_release,
+    },
+    window_update_blocker::scm::{
+        ServiceController, ServiceSpec,
+        ServiceState, StartType, WindowsServiceController, SERVICE_ERROR_NORMAL,
+        SERVICE_WIN32_OWN_PROCESS,
+    },
+    window_update_blocker::{
+        os::windows::is_elevated, reconfigure, serv_get_config, serv_install, serv_start, serv_stop,
+        serv_notify, serv_uninstall, serv_wait_for_progress, ServiceStatusEx, StatusReporter, SERVICE_NAME,
+    },
+    windows_service::{
+        define_windows_service,
+        service::{
+            PowerEventParam, ServiceControl, ServiceState as WinServiceState, ServiceStatus,
+            SessionChangeReason,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+    },
 };
-use window_update_blocker::{Logging, ServiceStatusEx, WindowsService, SERVICE_TYPE};
 
-const SERVICE_NAME: &str = "WindowsUpdateBlocker.rs";
 const SERVICE_DESCRIPTION: &str = "Blocker for Windows Update";
+#[cfg(windows)]
 const SERVICE_DISPLAY: &str = "Blocker for Windows Update";
+#[cfg(windows)]
 const SERVICE_ARGUMENTS: &[&'static str] = &["run"];
 
+/// How long the SCM waits for the next checkpoint of a pending state.
+#[cfg(windows)]
+const PENDING_WAIT_HINT: Duration = Duration::from_secs(10);
+/// How often a long pending transition re-reports its progress.
+const PENDING_REPORT_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Config path handed from `run` to the service main, which the SCM calls without it.
+#[cfg(windows)]
+static CONFIG_PATH: OnceCell<Option<PathBuf>> = OnceCell::new();
+
 #[derive(Parser, Debug)]
 #[command(name = "window_update_blocker")]
 #[command(author = "gecko <577738@qq.com>")]
@@ -33,46 +78,181 @@ pub struct Args {
     #[clap(flatten)]
     output: Logging,
 
+    /// Path to the config file (defaults to config.toml in %ProgramFiles%\WindowsUpdateBlocker)
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Print the changes that would be made without making them
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Manage the service on another computer, by host name or address
+    #[clap(long, global = true, value_name = "HOST")]
+    computer: Option<String>,
+
     #[clap(subcommand)]
     cmd: Option<Cmd>,
 }
 
 impl Args {
     fn execute(self) -> Result<(), anyhow::Error> {
-        let Args { cmd, output } = self;
+        let Args {
+            cmd,
+            mut output,
+            config,
+            dry_run,
+            computer,
+        } = self;
 
+        // The console mode logs to the terminal unless told to be quiet.
+        if matches!(cmd, Some(Cmd::Run { console: true })) && output.verbose == 0 && !output.quiet {
+            output.verbose = 1;
+        }
         output.initialize_logging();
         own_logger::set_panic_hook();
 
-        match cmd {
+        #[cfg(not(windows))]
+        if computer.is_some() {
+            return Err(anyhow!("--computer is only available on Windows"));
+        }
+        #[cfg(windows)]
+        let scm = match &computer {
+            Some(host) => {
+                if let Some(cmd) = cmd.as_ref().filter(|cmd| !cmd.supports_remote()) {
+                    return Err(anyhow!("{} cannot be run on another computer", cmd.name()));
+                }
+                WindowsServiceController::remote(host)
+            }
+            None => WindowsServiceController::local(),
+        };
+
+        let result = match cmd {
+            #[cfg(windows)]
+            Some(Cmd::Install(options)) if dry_run => {
+                let config = install_config(config);
+                let settings = Config::load(config.as_deref())?;
+                let (service_dir, copy_dir) = install_dirs(&scm);
+                println!("would copy {:?} to {}", env::current_exe()?, copy_dir.exe().display());
+                if let Some(config) = &config {
+                    println!("would copy {} to {}", config.display(), copy_dir.config().display());
+                }
+                println!("would install {} running {}", SERVICE_NAME, service_dir.exe().display());
+                println!(
+                    "as {}, depending on {:?}, with {:?}",
+                    options.account.as_deref().unwrap_or("LocalSystem"),
+                    options.depend_on,
+                    options.service_options()
+                );
+                if let Some(permissions) = &settings.service_permissions {
+                    println!("would set the permissions of {} to {:?}", SERVICE_NAME, permissions);
+                }
+                Ok(())
+            }
+            #[cfg(windows)]
+            Some(Cmd::Uninstall { restore }) if dry_run => {
+                println!("would uninstall {}", SERVICE_NAME);
+                if restore {
+                    plan_restore()?;
+                }
+                Ok(())
+            }
+            #[cfg(windows)]
+            Some(Cmd::Restore) if dry_run => plan_restore(),
+            #[cfg(windows)]
+            Some(Cmd::Start) if dry_run => {
+                println!("would start {}", SERVICE_NAME);
+                Ok(())
+            }
+            #[cfg(windows)]
+            Some(Cmd::Stop) if dry_run => {
+                println!("would stop {}", SERVICE_NAME);
+                Ok(())
+            }
+            #[cfg(windows)]
+            Some(Cmd::Upgrade) if dry_run => {
+                let dir = InstallDir::managed();
+                println!("would stop {}", SERVICE_NAME);
+                println!("would replace {} with {:?}", dir.exe().display(), env::current_exe()?);
+                println!("would start {}, rolling back if it fails", SERVICE_NAME);
+                Ok(())
+            }
+            Some(Cmd::Run { .. }) if dry_run => plan(Config::load(config.as_deref())?),
+            #[cfg(windows)]
+            Some(Cmd::Enforce) if dry_run => {
+                println!("would send {:?} to {}", UserEvent::EnforceNow, SERVICE_NAME);
+                Ok(())
+            }
+            #[cfg(windows)]
+            Some(Cmd::Reload) if dry_run => {
+                println!("would send {:?} to {}", UserEvent::ReloadConfig, SERVICE_NAME);
+                Ok(())
+            }
+            #[cfg(windows)]
+            Some(Cmd::RotateLogs) if dry_run => {
+                println!("would send {:?} to {}", UserEvent::RotateLogs, SERVICE_NAME);
+                Ok(())
+            }
             #[cfg(windows)]
-            Some(Cmd::Install) => {
+            Some(Cmd::Install(options)) => {
                 if !is_elevated() {
                     return Err(anyhow::Error::msg("the program isn’t running as elevated"));
                 }
-                match uninstall() {
-                    Ok(_) => {
-                        install()?;
-                    }
+                let config = install_config(config);
+                // Refuse to install with a config the service could not start with.
+                Config::load(config.as_deref())?;
+                match serv_uninstall(&scm, SERVICE_NAME) {
+                    Ok(()) | Err(ServiceError::NotFound) => install(config, options, &scm),
                     Err(e) => {
                         error!("Service uninstall error: {}", e);
+                        Err(e.into())
                     }
                 }
-
-                Ok(())
             }
             #[cfg(windows)]
-            Some(Cmd::Uninstall) => uninstall(),
+            Some(Cmd::Upgrade) => {
+                if !is_elevated() {
+                    return Err(anyhow::Error::msg("the program isn’t running as elevated"));
+                }
+                upgrade()
+            }
+            #[cfg(windows)]
+            Some(Cmd::Uninstall { restore: false }) => uninstall(&scm),
+            #[cfg(windows)]
+            Some(Cmd::Uninstall { restore: true }) => {
+                match serv_uninstall(&WindowsServiceController::local(), SERVICE_NAME) {
+                    Ok(()) | Err(ServiceError::NotFound) => restore(),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            #[cfg(windows)]
+            Some(Cmd::Restore) => restore(),
             #[cfg(windows)]
-            Some(Cmd::Start) => start(),
+            Some(Cmd::Status) => status(Config::load(config.as_deref())?, &scm),
             #[cfg(windows)]
-            Some(Cmd::Stop) => stop(),
+            Some(Cmd::Start) => start(&scm),
+            #[cfg(windows)]
+            Some(Cmd::Stop) => stop(&scm),
+            Some(Cmd::Run { console: true }) => run_console(config),
+            #[cfg(windows)]
+            Some(Cmd::Run { console: false }) => run(config),
+            #[cfg(not(windows))]
+            Some(Cmd::Run { console: false }) => {
+                Err(anyhow!("only `run --console` is available outside Windows"))
+            }
             #[cfg(windows)]
-            Some(Cmd::Run) => run(),
+            Some(Cmd::Enforce) => notify(&scm, UserEvent::EnforceNow),
+            #[cfg(windows)]
+            Some(Cmd::Reload) => notify(&scm, UserEvent::ReloadConfig),
+            #[cfg(windows)]
+            Some(Cmd::RotateLogs) => notify(&scm, UserEvent::RotateLogs),
 
             None => Ok({
                 // std::process::exit(0);
             }),
+        };
+        match computer {
+            Some(host) => result.map_err(|e| e.context(format!("on {}", host))),
+            None => result,
         }
     }
 }
@@ -80,22 +260,88 @@ impl Args {
 #[derive(Parser, Debug)]
 /// The options for the wasmer Command Line Interface
 enum Cmd {
+    /// Copy the binary and config to %ProgramFiles%\WindowsUpdateBlocker and install the service
+    #[cfg(windows)]
+    Install(InstallOptions),
+    /// Replace the installed binary with this one and restart the service, rolling back on failure
     #[cfg(windows)]
-    Install,
+    Upgrade,
+    #[cfg(windows)]
+    Uninstall {
+        /// Also put every changed service, registry value and task back to its original state
+        #[clap(long)]
+        restore: bool,
+    },
+    /// Put every changed service, registry value and task back to its original state;
+    /// the blocker service is disabled until the next install
     #[cfg(windows)]
-    Uninstall,
+    Restore,
+    /// Show whether every blocked target is still compliant; exits with 3 on drift
+    #[cfg(windows)]
+    Status,
     #[cfg(windows)]
     Start,
     #[cfg(windows)]
     Stop,
+    /// Run the enforcement loop; started by the service manager unless `--console` is given
+    Run {
+        /// Run in the foreground, logging to the terminal, until Ctrl+C or SIGTERM
+        #[clap(long)]
+        console: bool,
+    },
+    /// Ask the running service to enforce now instead of waiting for the schedule
+    #[cfg(windows)]
+    Enforce,
+    /// Ask the running service to reload its config file
     #[cfg(windows)]
-    Run,
+    Reload,
+    /// Ask the running service to start a new log file
+    #[cfg(windows)]
+    RotateLogs,
+}
+
+#[cfg(windows)]
+impl Cmd {
+    /// Whether the command only talks to the service manager, so `--computer` can redirect it.
+    fn supports_remote(&self) -> bool {
+        matches!(
+            self,
+            Cmd::Install(_)
+                | Cmd::Uninstall { restore: false }
+                | Cmd::Status
+                | Cmd::Start
+                | Cmd::Stop
+                | Cmd::Enforce
+                | Cmd::Reload
+                | Cmd::RotateLogs
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Cmd::Install(_) => "install",
+            Cmd::Upgrade => "upgrade",
+            Cmd::Uninstall { restore: true } => "uninstall --restore",
+            Cmd::Uninstall { restore: false } => "uninstall",
+            Cmd::Restore => "restore",
+            Cmd::Status => "status",
+            Cmd::Start => "start",
+            Cmd::Stop => "stop",
+            Cmd::Run { .. } => "run",
+            Cmd::Enforce => "enforce",
+            Cmd::Reload => "reload",
+            Cmd::RotateLogs => "rotate-logs",
+        }
+    }
 }
 
 fn main() {
     match Args::try_parse() {
         Ok(args) => {
-            let _ = args.execute();
+            if let Err(e) = args.execute() {
+                eprintln!("error: {:#}", e);
+                std::process::exit(exit_code(&e));
+            }
         }
         Err(e) => {
             let _ = matches!(
@@ -107,64 +353,501 @@ fn main() {
     }
 }
 
-pub fn install() -> anyhow::Result<()> {
-    let agent_serv = WindowsService {
+/// Exit code of `status` when a target drifted.
+const EXIT_DRIFTED: i32 = 3;
+
+/// `status` found targets that are not compliant.
+// Only `status` reports it, which is Windows only.
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug)]
+struct Drifted(usize);
+
+impl fmt::Display for Drifted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} targets drifted", self.0)
+    }
+}
+
+impl std::error::Error for Drifted {}
+
+/// Exit code for a failed subcommand: [`EXIT_DRIFTED`] when `status` found
+/// drift, the [`ServiceError`] exit code when the failure came from the SCM,
+/// 1 otherwise. Usage errors exit with 2 from clap.
+fn exit_code(err: &anyhow::Error) -> i32 {
+    for cause in err.chain() {
+        if cause.is::<Drifted>() {
+            return EXIT_DRIFTED;
+        }
+        if let Some(e) = cause.downcast_ref::<ServiceError>() {
+            return e.exit_code();
+        }
+    }
+    1
+}
+
+/// Service settings applied by `install`.
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(clap::Args, Debug)]
+pub struct InstallOptions {
+    /// Description shown in the services console
+    #[clap(long, default_value = SERVICE_DESCRIPTION)]
+    description: String,
+    /// Start shortly after the other auto-start services
+    #[clap(long)]
+    delayed_start: bool,
+    /// Restart the service this many seconds after a failure; repeat for the
+    /// second, third, ... failure, the last delay applies to every later one
+    #[clap(long, value_name = "SECONDS")]
+    restart_after: Vec<u64>,
+    /// Reset the failure count after this many seconds without a failure
+    #[clap(long, value_name = "SECONDS", default_value_t = 86400)]
+    reset_period: u64,
+    /// Also restart when the service stops with an error instead of crashing
+    #[clap(long)]
+    failure_actions_on_non_crash: bool,
+    /// Service SID type: none, unrestricted or restricted
+    #[clap(long, value_name = "TYPE")]
+    sid_type: Option<SidType>,
+    /// Account to run the service as (defaults to LocalSystem)
+    #[clap(long)]
+    account: Option<String>,
+    /// Read the account password from this environment variable
+    #[clap(long, value_name = "VAR", requires = "account", conflicts_with = "password_stdin")]
+    password_env: Option<String>,
+    /// Read the account password from the first line of stdin
+    #[clap(long, requires = "account")]
+    password_stdin: bool,
+    /// Service that has to be running before the blocker starts; may be repeated
+    #[clap(long, value_name = "SERVICE")]
+    depend_on: Vec<String>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl InstallOptions {
+    /// The account password; it is never taken from the command line.
+    fn password(&self) -> anyhow::Result<Option<OsString>> {
+        if let Some(var) = &self.password_env {
+            return env::var_os(var)
+                .map(Some)
+                .ok_or_else(|| anyhow!("environment variable {} is not set", var));
+        }
+        if self.password_stdin {
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .context("Failed to read the password from stdin")?;
+            return Ok(Some(OsString::from(line.trim_end_matches(['\r', '\n']))));
+        }
+        Ok(None)
+    }
+
+    fn service_options(&self) -> ServiceOptions {
+        ServiceOptions {
+            description: Some(OsString::from(&self.description)),
+            delayed_auto_start: Some(self.delayed_start),
+            failure_actions: (!self.restart_after.is_empty()).then(|| FailureActions {
+                restart_delays: self
+                    .restart_after
+                    .iter()
+                    .map(|secs| Duration::from_secs(*secs))
+                    .collect(),
+                reset_period: Some(Duration::from_secs(self.reset_period)),
+            }),
+            failure_actions_on_non_crash: Some(self.failure_actions_on_non_crash),
+            sid_type: self.sid_type,
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn install(
+    config: Option<PathBuf>,
+    options: InstallOptions,
+    ctl: &WindowsServiceController,
+) -> anyhow::Result<()> {
+    // The installed service loads the config next to its binary, so no `--config` is passed.
+    let (dir, copy_dir) = install_dirs(ctl);
+    let permissions = Config::load(config.as_deref())?.service_permissions;
+    copy_dir.deploy(&env::current_exe()?, config.as_deref())?;
+    let arguments: Vec<OsString> = SERVICE_ARGUMENTS.iter().map(OsString::from).collect();
+
+    let service_info = ServiceSpec {
         name: OsString::from(SERVICE_NAME),
         display_name: OsString::from(SERVICE_DISPLAY),
-        executable_path: env::current_exe().unwrap_or_default(),
-        arguments: SERVICE_ARGUMENTS
-            .into_iter()
-            .map(|x| OsString::from(x))
-            .rev()
-            .collect(),
+        service_type: SERVICE_WIN32_OWN_PROCESS,
+        start_type: StartType::AutoStart,
+        error_control: SERVICE_ERROR_NORMAL,
+        executable_path: dir.exe(),
+        launch_arguments: arguments,
+        dependencies: options.depend_on.iter().map(OsString::from).collect(),
+        account_name: options.account.as_ref().map(OsString::from),
+        // account_name: Some(OsString::from(r#"NT AUTHORITY\NetworkService"#)),
+        account_password: options.password()?,
+        options: options.service_options(),
     };
+    serv_install(ctl, service_info)?;
+    println!("installed {} to {}", SERVICE_NAME, copy_dir.dir().display());
 
-    let service_info = ServiceInfo {
-        name: agent_serv.name.clone(),
-        display_name: agent_serv.display_name.clone(),
-        service_type: SERVICE_TYPE,
-        start_type: ServiceStartType::AutoStart,
-        error_control: ServiceErrorControl::Normal,
-        executable_path: agent_serv.executable_path.clone(),
-        launch_arguments: agent_serv.arguments.clone(),
-        dependencies: vec![],
-        account_name: None,
-        // account_name: Some(OsString::from(r#"NT AUTHORITY\NetworkService"#)),
-        account_password: None,
+    // The service re-checks these every cycle, applying them now closes the gap until then.
+    if let Some(permissions) = &permissions {
+        let host = Host {
+            services: Arc::new(ctl.clone()),
+            ..Host::memory()
+        };
+        ServiceDacl::new(SERVICE_NAME, permissions)
+            .apply(&host)
+            .context("Failed to set the service permissions")?;
+        println!("set the permissions of {}", SERVICE_NAME);
+    }
+    Ok(())
+}
+
+/// The config `install` copies: `--config`, or else a config.toml next to the
+/// installer. Without either the installed config, if any, is kept.
+#[cfg(windows)]
+fn install_config(config: Option<PathBuf>) -> Option<PathBuf> {
+    config.or_else(|| Some(InstallDir::current().config()).filter(|path| path.exists()))
+}
+
+/// The install directory as the service manager of `ctl` sees it, and the
+/// same directory as reachable from here for copying the files.
+#[cfg(windows)]
+fn install_dirs(ctl: &WindowsServiceController) -> (InstallDir, InstallDir) {
+    match ctl.computer() {
+        Some(computer) => InstallDir::remote(&computer.to_string_lossy()),
+        None => (InstallDir::managed(), InstallDir::managed()),
+    }
+}
+
+/// Replaces the installed binary with the running one and restarts the service.
+///
+/// The service is registered again when it ran from another path or with other
+/// arguments. The new binary gets as long as it reports progress to reach
+/// Running, its first pass keeps it in StartPending; if it does not get there,
+/// the previous binary and registration are put back and started.
+#[cfg(windows)]
+pub fn upgrade() -> anyhow::Result<()> {
+    let ctl = WindowsServiceController::local();
+    let dir = InstallDir::managed();
+    let exe = env::current_exe()?;
+    if dir.is_installed_exe(&exe) {
+        return Err(anyhow!("run upgrade from the new binary, not from {}", exe.display()));
+    }
+
+    let previous = serv_get_config(&ctl, SERVICE_NAME)?;
+    serv_stop(&ctl, SERVICE_NAME)?;
+    let swap = match dir.swap_binary(&exe) {
+        Ok(swap) => swap,
+        Err(e) => {
+            error!("upgrade failed: {:#}", e);
+            serv_start(&ctl, SERVICE_NAME)?;
+            return Err(e.context("upgrade failed, the previous binary was kept"));
+        }
     };
-    let _ = serv_install(agent_serv, service_info);
+
+    let mut command = OsString::from(format!("\"{}\"", dir.exe().display()));
+    for arg in SERVICE_ARGUMENTS {
+        command.push(" ");
+        command.push(arg);
+    }
+    let result = reconfigure(&ctl, SERVICE_NAME, previous.start_type, Some(&PathBuf::from(command)))
+        .and_then(|()| {
+            ctl.start(SERVICE_NAME)?;
+            serv_wait_for_progress(&ctl, SERVICE_NAME, ServiceState::Running)?;
+            Ok(())
+        });
+    match result {
+        Ok(()) => {
+            swap.commit();
+            println!("upgraded {} to {}", SERVICE_NAME, env!("CARGO_PKG_VERSION"));
+            Ok(())
+        }
+        Err(e) => {
+            error!("upgrade failed: {:#}", e);
+            let _ = serv_stop(&ctl, SERVICE_NAME);
+            swap.rollback()?;
+            reconfigure(&ctl, SERVICE_NAME, previous.start_type, Some(&previous.executable_path))?;
+            serv_start(&ctl, SERVICE_NAME)?;
+            Err(e.context("upgrade failed, the previous binary was restored"))
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn uninstall(ctl: &WindowsServiceController) -> anyhow::Result<()> {
+    serv_uninstall(ctl, SERVICE_NAME)?;
+    Ok(())
+}
+/// Puts back the original state recorded in the snapshot.
+///
+/// The blocker service, if still installed, is stopped and disabled first, so
+/// it re-applies nothing now or after a reboot; `install` enables it again.
+#[cfg(windows)]
+pub fn restore() -> anyhow::Result<()> {
+    let ctl = WindowsServiceController::local();
+    let _ = serv_stop(&ctl, SERVICE_NAME);
+    match reconfigure(&ctl, SERVICE_NAME, StartType::Disabled, None) {
+        Ok(()) => println!(
+            "{} disabled, run install to enforce again or uninstall to remove it",
+            SERVICE_NAME
+        ),
+        Err(e) if matches!(e.downcast_ref::<ServiceError>(), Some(ServiceError::NotFound)) => {}
+        Err(e) => return Err(e.context("Failed to disable the blocker service")),
+    }
+
+    let snapshot = Snapshot::load(&Snapshot::default_path())?;
+    if snapshot.is_empty() {
+        println!("nothing to restore");
+        return Ok(());
+    }
+    let report = snapshot.restore(&Host::windows());
+    for rule in &report.rules {
+        match &rule.status {
+            RuleStatus::Compliant => println!("{}: unchanged", rule.rule),
+            RuleStatus::Absent => println!("{}: not present", rule.rule),
+            RuleStatus::Fixed { observed } => println!("{}: restored (was {})", rule.rule, observed),
+            RuleStatus::Failed(e) => println!("{}: FAILED: {}", rule.rule, e),
+        }
+    }
+    if report.failed() > 0 {
+        return Err(anyhow!("restore incomplete: {} targets failed", report.failed()));
+    }
+    snapshot.remove()
+}
+/// Prints what one enforcement cycle would change.
+pub fn plan(config: Config) -> anyhow::Result<()> {
+    let report = Policy::from_config(&config).check(&local_host());
+    let mut renewals = 0;
+    for rule in &report.rules {
+        match &rule.compliance {
+            Ok(Compliance::Compliant | Compliance::Absent) => {}
+            Ok(Compliance::Drifted { expected, observed }) => {
+                println!("would change {}: {} -> {}", rule.rule, observed, expected)
+            }
+            Ok(Compliance::Expiring { observed }) => {
+                renewals += 1;
+                println!("would renew {}: {}", rule.rule, observed)
+            }
+            Err(e) => println!("cannot check {}: {}", rule.rule, e),
+        }
+    }
+    if report.is_compliant() && renewals == 0 {
+        println!("nothing to change");
+    }
     Ok(())
 }
 
-pub fn uninstall() -> anyhow::Result<()> {
-    let _ = serv_uninstall(SERVICE_NAME);
+/// Prints what `restore` would change.
+#[cfg(windows)]
+pub fn plan_restore() -> anyhow::Result<()> {
+    println!("would stop and disable {}", SERVICE_NAME);
+    let snapshot = Snapshot::load(&Snapshot::default_path())?;
+    let report = snapshot.plan_restore(&Host::windows());
+    for rule in &report.rules {
+        match &rule.status {
+            RuleStatus::Compliant | RuleStatus::Absent => {}
+            RuleStatus::Fixed { observed } => {
+                println!("would restore {}: {} -> original", rule.rule, observed)
+            }
+            RuleStatus::Failed(e) => println!("cannot restore {}: {}", rule.rule, e),
+        }
+    }
+    if report.fixed() + report.failed() == 0 {
+        println!("nothing to restore");
+    }
     Ok(())
 }
-pub fn start() -> anyhow::Result<()> {
-    let _ = serv_start(SERVICE_NAME);
+
+#[cfg(windows)]
+pub fn status(config: Config, ctl: &WindowsServiceController) -> anyhow::Result<()> {
+    let mut drifted = 0;
+
+    match serv_get_config(ctl, SERVICE_NAME) {
+        Ok(_) => {
+            let state = ctl.query_status(SERVICE_NAME)?.current_state;
+            println!("{}: installed, {:?}", SERVICE_NAME, state);
+            if state != ServiceState::Running {
+                drifted += 1;
+            }
+        }
+        Err(ServiceError::NotFound) => {
+            println!("{}: not installed", SERVICE_NAME);
+            drifted += 1;
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut host = Host::windows();
+    let mut policy = Policy::from_config(&config);
+    let remote = ctl.computer().is_some();
+    if remote {
+        // Only services are reachable on another computer.
+        println!("registry, task and process targets are only checked on the local computer");
+        policy.retain(|rule| {
+            let id = rule.id();
+            id.starts_with("service:") || id.starts_with("service-dacl:")
+        });
+        host.services = Arc::new(ctl.clone());
+    }
+    let report = policy.check(&host);
+    for rule in &report.rules {
+        let detail = match rule.rule.strip_prefix("service:") {
+            Some(name) => match (host.services.query_config(name), host.services.query_status(name)) {
+                (Ok(config), Ok(status)) => {
+                    format!(" [{:?}/{:?}]", config.start_type, status.current_state)
+                }
+                _ => " [not installed]".to_owned(),
+            },
+            None => String::new(),
+        };
+        match &rule.compliance {
+            Ok(c) if c.is_compliant() => println!("{}{}: {}", rule.rule, detail, c),
+            Ok(drift) => println!("{}{}: {}", rule.rule, detail, drift),
+            Err(e) => println!("{}{}: check failed: {}", rule.rule, detail, e),
+        }
+    }
+    drifted += report.drifted();
+
+    if let Some(target) = config.update_policy.target_release.as_ref().filter(|_| !remote) {
+        if let Some((product, version)) = running_release(host.registry.as_ref())? {
+            if target.is_behind(product, &version) {
+                println!(
+                    "warning: running {} {}, newer than the pinned {}; Windows Update does not roll back",
+                    product.name(),
+                    version,
+                    target
+                );
+            }
+        }
+    }
+
+    if !remote {
+        let tamper = TamperLog::open(&TamperLog::default_path())?;
+        for (target, count) in tamper.counts() {
+            println!("{}: reverted by Windows {} times", target, count);
+        }
+    }
+
+    if drifted > 0 {
+        return Err(Drifted(drifted).into());
+    }
+    println!("all targets compliant");
     Ok(())
 }
-pub fn stop() -> anyhow::Result<()> {
-    let _ = serv_stop(SERVICE_NAME);
+#[cfg(windows)]
+pub fn start(ctl: &WindowsServiceController) -> anyhow::Result<()> {
+    serv_start(ctl, SERVICE_NAME)?;
+    Ok(())
+}
+#[cfg(windows)]
+pub fn stop(ctl: &WindowsServiceController) -> anyhow::Result<()> {
+    serv_stop(ctl, SERVICE_NAME)?;
+    Ok(())
+}
+
+/// Sends a user-defined control code to the running service.
+#[cfg(windows)]
+pub fn notify(ctl: &WindowsServiceController, event: UserEvent) -> anyhow::Result<()> {
+    serv_notify(ctl, SERVICE_NAME, event)?;
+    println!("sent {:?} to {}", event, SERVICE_NAME);
+    Ok(())
+}
+
+/// The backends of this machine; outside Windows there is nothing to block.
+fn local_host() -> Host {
+    #[cfg(windows)]
+    return Host::windows();
+    #[cfg(not(windows))]
+    return Host::memory();
+}
+
+/// The enforcer for this machine. Outside Windows the host is fake, so the
+/// snapshot and tamper log stay in memory instead of next to the binary.
+fn local_enforcer(config: &Config) -> anyhow::Result<Enforcer> {
+    #[cfg(windows)]
+    return Ok(Enforcer::new(
+        Host::windows(),
+        config,
+        Snapshot::load(&Snapshot::default_path())?,
+        TamperLog::open(&TamperLog::default_path())?,
+    ));
+    #[cfg(not(windows))]
+    return Ok(Enforcer::new(
+        Host::memory(),
+        config,
+        Snapshot::memory(),
+        TamperLog::memory(),
+    ));
+}
+
+/// Runs the enforcement loop in the foreground until Ctrl+C or SIGTERM.
+pub fn run_console(config_path: Option<PathBuf>) -> anyhow::Result<()> {
+    let config = Config::load(config_path.as_deref())?;
+    info!("config loaded: {:?}", config);
+    let enforcer = local_enforcer(&config)?;
+
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let token = CancellationToken::new();
+        tokio::spawn(cancel_on_signal(token.clone()));
+        // Controls only come from the service manager; the sender keeps the channel open.
+        let (_control_tx, control_rx) = mpsc::unbounded_channel();
+        let status = Arc::new(ConsoleStatus);
+        serv_executor(token, config, config_path, control_rx, status, enforcer).await
+    })?;
+    info!("stopped");
     Ok(())
 }
 
-pub fn run() -> anyhow::Result<()> {
+/// Cancels `token` on Ctrl+C, or on SIGTERM where there is one.
+async fn cancel_on_signal(token: CancellationToken) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {},
+                    _ = term.recv() => {},
+                }
+            }
+            Err(e) => {
+                warn!("cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+
+    info!("shutdown signal received");
+    token.cancel();
+}
+
+#[cfg(windows)]
+pub fn run(config: Option<PathBuf>) -> anyhow::Result<()> {
+    let _ = CONFIG_PATH.set(config);
     define_windows_service!(ffi_service_main, my_service_main);
     Ok(service_dispatcher::start(SERVICE_NAME, ffi_service_main)?)
 }
 
+#[cfg(windows)]
 pub fn my_service_main(_arguments: Vec<OsString>) {
     if let Err(e) = run_service(_arguments) {
         error!("error: {}", e);
     }
 }
 
+#[cfg(windows)]
 pub fn run_service(arguments: Vec<OsString>) -> anyhow::Result<()> {
     info!("service start {:?}", arguments);
     // Create a cancellation token to be able to cancell server
     let control_token = CancellationToken::new();
     let server_token = control_token.child_token();
+    // Controls the executor has to act on and report status for.
+    let (control_tx, control_rx) = mpsc::unbounded_channel();
 
     // Define system service event handler that will be receiving service events.
     let event_handler = move |control_event| -> ServiceControlHandlerResult {
@@ -180,6 +863,56 @@ pub fn run_service(arguments: Vec<OsString>) -> anyhow::Result<()> {
                 ServiceControlHandlerResult::NoError
             }
 
+            ServiceControl::Pause => {
+                info!("service pause event received");
+                let _ = control_tx.send(Control::Pause);
+                ServiceControlHandlerResult::NoError
+            }
+
+            ServiceControl::Continue => {
+                info!("service continue event received");
+                let _ = control_tx.send(Control::Continue);
+                ServiceControlHandlerResult::NoError
+            }
+
+            ServiceControl::Preshutdown => {
+                info!("service preshutdown event received");
+                let _ = control_tx.send(Control::Preshutdown);
+                ServiceControlHandlerResult::NoError
+            }
+
+            ServiceControl::PowerEvent(
+                PowerEventParam::ResumeSuspend | PowerEventParam::ResumeAutomatic,
+            ) => {
+                let _ = control_tx.send(Control::Trigger(Trigger::Resume));
+                ServiceControlHandlerResult::NoError
+            }
+
+            ServiceControl::SessionChange(param) => {
+                match param.reason {
+                    SessionChangeReason::SessionLogon => {
+                        let _ = control_tx.send(Control::Trigger(Trigger::Logon));
+                    }
+                    SessionChangeReason::SessionUnlock => {
+                        let _ = control_tx.send(Control::Trigger(Trigger::Unlock));
+                    }
+                    _ => {}
+                }
+                ServiceControlHandlerResult::NoError
+            }
+
+            // Accepted for resume, the other power events need no answer.
+            ServiceControl::PowerEvent(_) => ServiceControlHandlerResult::NoError,
+
+            ServiceControl::UserEvent(code) => match UserEvent::from_raw(code.to_raw()) {
+                Some(event) => {
+                    info!("service {:?} event received", event);
+                    let _ = control_tx.send(Control::User(event));
+                    ServiceControlHandlerResult::NoError
+                }
+                None => ServiceControlHandlerResult::NotImplemented,
+            },
+
             _ => ServiceControlHandlerResult::NotImplemented,
         }
     };
@@ -187,30 +920,47 @@ pub fn run_service(arguments: Vec<OsString>) -> anyhow::Result<()> {
     // Register system service event handler.
     // The returned status handle should be used to report service status changes to the system.
     let status_handle = service_control_handler::register(SERVICE_NAME, event_handler)?;
+    let status = Arc::new(StatusReporter::new(status_handle));
+    status.pending(WinServiceState::StartPending, PENDING_WAIT_HINT)?;
 
-    // Tell the system that service is running
-    status_handle.set_service_status(ServiceStatus::running())?;
-    info!("service running");
+    let config_path = CONFIG_PATH.get().cloned().flatten();
+    let config = match Config::load(config_path.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("config error: {:#}", e);
+            status.set(ServiceStatus::stopped_with_error(3))?;
+            return Err(e);
+        }
+    };
+    info!("config loaded: {:?}", config);
+    status.pending(WinServiceState::StartPending, PENDING_WAIT_HINT)?;
 
     match tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build() {
             Ok(rt) => {
+                let executor_status = status.clone();
                 match std::thread::spawn(move || {
-                    rt.block_on(async { 
-                        serv_executor(server_token).await
+                    rt.block_on(async {
+                        let enforcer = local_enforcer(&config)?;
+                        serv_executor(server_token, config, config_path, control_rx, executor_status, enforcer).await
                     })
                 }).join() {
-                    Ok(_) => {
+                    Ok(Ok(())) => {
                         info!("server thread stoped");
                         // Tell the system that service has stopped.
-                        status_handle.set_service_status(ServiceStatus::stopped())?;
+                        status.set(ServiceStatus::stopped())?;
                                             info!("service stoped");
                         Ok(())
                     },
+                    Ok(Err(e)) => {
+                        error!("server error: {:#}", e);
+                        status.set(ServiceStatus::stopped_with_error(4))?;
+                        Err(e)
+                    },
                     Err(e) => {
                         error!("server panic: {:#?}", e);
-                        status_handle.set_service_status(ServiceStatus::stopped_with_error(1))?;
+                        status.set(ServiceStatus::stopped_with_error(1))?;
                         return Err(anyhow!("server panic"));
                     },
                 }
@@ -218,13 +968,174 @@ pub fn run_service(arguments: Vec<OsString>) -> anyhow::Result<()> {
             },
             Err(e) => {
                 error!("server error: {:#?}", e);
-                status_handle.set_service_status(ServiceStatus::stopped_with_error(2))?;
+                status.set(ServiceStatus::stopped_with_error(2))?;
                 return Err(anyhow!("server error"));
             },
         }
 }
 
-async fn serv_executor(token: CancellationToken) -> anyhow::Result<()> {
+/// A state change of the executor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    StartPending,
+    StopPending,
+    PausePending,
+    ContinuePending,
+    Running,
+    Paused,
+}
+
+/// Where the executor reports its state changes.
+trait StatusSink: Send + Sync {
+    fn report(&self, phase: Phase) -> anyhow::Result<()>;
+}
+
+/// Reports to the SCM; pending phases count up the checkpoint.
+#[cfg(windows)]
+impl StatusSink for StatusReporter {
+    fn report(&self, phase: Phase) -> anyhow::Result<()> {
+        match phase {
+            Phase::StartPending => self.pending(WinServiceState::StartPending, PENDING_WAIT_HINT),
+            Phase::StopPending => self.pending(WinServiceState::StopPending, PENDING_WAIT_HINT),
+            Phase::PausePending => self.pending(WinServiceState::PausePending, PENDING_WAIT_HINT),
+            Phase::ContinuePending => {
+                self.pending(WinServiceState::ContinuePending, PENDING_WAIT_HINT)
+            }
+            Phase::Running => self.set(ServiceStatus::running()),
+            Phase::Paused => self.set(ServiceStatus::paused()),
+        }?;
+        Ok(())
+    }
+}
+
+/// `run --console` has no service manager to report to.
+struct ConsoleStatus;
+
+impl StatusSink for ConsoleStatus {
+    fn report(&self, phase: Phase) -> anyhow::Result<()> {
+        debug!("{:?}", phase);
+        Ok(())
+    }
+}
+
+/// Drives `fut` to completion while re-reporting the pending `phase`, so the
+/// SCM sees progress however long it takes.
+async fn report_while<F: Future>(
+    status: &dyn StatusSink,
+    phase: Phase,
+    fut: F,
+) -> anyhow::Result<F::Output> {
+    tokio::pin!(fut);
+    let mut ticker = tokio::time::interval(PENDING_REPORT_INTERVAL);
+    loop {
+        tokio::select! {
+            out = &mut fut => return Ok(out),
+            _ = ticker.tick() => status.report(phase)?,
+        }
+    }
+}
+
+/// Service controls forwarded from the SCM event handler to the executor.
+// Only the SCM event handler sends them, `run --console` never does.
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Pause,
+    Continue,
+    /// Stop after a final pass, if the preshutdown trigger is enabled.
+    Preshutdown,
+    Trigger(Trigger),
+    User(UserEvent),
+}
+
+/// Everything an enforcement pass needs, shared by the scheduled job and
+/// the passes run on demand.
+#[derive(Clone)]
+struct Enforcer {
+    host: Host,
+    /// Swapped when the config is reloaded.
+    policy: Arc<Mutex<Arc<Policy>>>,
+    snapshot: Arc<Mutex<Snapshot>>,
+    tamper: Arc<Mutex<TamperLog>>,
+    paused: Arc<AtomicBool>,
+    /// Held for the duration of a pass, so pausing can wait for it.
+    busy: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Enforcer {
+    fn new(host: Host, config: &Config, snapshot: Snapshot, tamper: TamperLog) -> Self {
+        Self {
+            host,
+            policy: Arc::new(Mutex::new(Arc::new(Policy::from_config(config)))),
+            snapshot: Arc::new(Mutex::new(snapshot)),
+            tamper: Arc::new(Mutex::new(tamper)),
+            paused: Arc::new(AtomicBool::new(false)),
+            busy: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    async fn cycle(self) {
+        let _busy = self.busy.lock().await;
+        if self.paused.load(Ordering::SeqCst) {
+            debug!("enforcement paused, skipping cycle");
+            return;
+        }
+        let policy = self.policy.lock().unwrap().clone();
+        match tokio::task::spawn_blocking(move || {
+            let report = policy.evaluate(&self.host, &mut self.snapshot.lock().unwrap());
+            self.tamper.lock().unwrap().observe(&report, chrono::Utc::now());
+            report
+        })
+        .await
+        {
+            Ok(report) => {
+                info!("enforcement cycle: {}", report);
+            }
+            Err(err) => {
+                error!("enforcement cycle panic: {}", err);
+            }
+        }
+    }
+
+    /// Stops further passes and waits for the one in progress, if any.
+    async fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        drop(self.busy.lock().await);
+    }
+
+    /// Allows passes again and runs one right away.
+    async fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.clone().cycle().await;
+    }
+}
+
+/// The scheduled enforcement job for the cron expression `schedule`.
+fn enforce_job(schedule: &str, enforcer: &Enforcer) -> anyhow::Result<Job> {
+    let enforcer = enforcer.clone();
+    Ok(Job::new_async(schedule, move |_uuid, _l| {
+        Box::pin(enforcer.clone().cycle())
+    })?)
+}
+
+/// Stops the scheduled job and waits for a pass that is still running.
+async fn drain(sched: &mut JobScheduler, enforcer: &Enforcer) {
+    if let Err(err) = sched.shutdown().await {
+        error!("Cancelled {:?}", err);
+    }
+    enforcer.pause().await;
+}
+
+/// Runs `enforcer` until `token` is cancelled or a preshutdown control
+/// arrives; a reload reads the config from `config_path` again.
+async fn serv_executor(
+    token: CancellationToken,
+    mut config: Config,
+    config_path: Option<PathBuf>,
+    mut controls: UnboundedReceiver<Control>,
+    status: Arc<dyn StatusSink>,
+    enforcer: Enforcer,
+) -> anyhow::Result<()> {
     let mut sched = JobScheduler::new().await.unwrap();
 
     sched.set_shutdown_handler(Box::new(|| {
@@ -233,61 +1144,391 @@ async fn serv_executor(token: CancellationToken) -> anyhow::Result<()> {
         })
     }));
 
-    sched
-    .add(
-        Job::new_async("0 */5 * * * *", |_uuid, _l| {
-            Box::pin(async move {
-                // let _ = serv_kill_update::kill();
-
-
-                let cmds = &[
-                    r#"Disable-ScheduledTask -TaskName "\Microsoft\Windows\WindowsUpdate\Scheduled Start" | Out-Null")"#,
-                    r#"if (!(Test-Path "HKLM:\Software\Microsoft\WindowsUpdate\UX\Settings")) { New-Item -Path "HKLM:\Software\Microsoft\WindowsUpdate\UX\Settings" -Force | Out-Null }"#,
-                    r#"Set-ItemProperty -Path "HKLM:\Software\Microsoft\WindowsUpdate\UX\Settings" -Name "UxOption" -Type DWord -Value 1"#,
-                    r#"Stop-Process -Name "MoUsoCoreWorker", "TiWorker" -Force -PassThru -ErrorAction SilentlyContinue | Out-Null"#,
-                    r#"Set-ItemProperty -Path "HKLM:\SYSTEM\ControlSet001\Services\WaaSMedicSvc" -Name Start -Value 4"#,
-                ];
-
-                cmds.iter().for_each(|cmd| {
-                    tokio::spawn(async move {
-                        match Command::new("powershell")
-                            .arg("-Command")
-                            .arg(cmd)
-                            .status()
-                            .await {
-                            Ok(_) => {
-                                info!("{}", cmd);
-                            },
-                            Err(err) => {
-                                error!("psh {}", err);
-                            }
-                        }
-                    });
-                });
-                
-
-            })
-        })
-        .unwrap(),
-    )
+    let mut job_id = sched
+    .add(enforce_job(&config.schedule, &enforcer)?)
     .await
     .expect("Should be able to add a job");
 
+// Run the first pass before reporting Running, so Running means blocked.
+report_while(status.as_ref(), Phase::StartPending, enforcer.clone().cycle()).await?;
 sched.start().await.unwrap();
+status.report(Phase::Running)?;
+info!("service running");
 
 
 loop {
     tokio::select! {
       _ = token.cancelled() => {
         // let _ = sched.shutdown().await;
-        if let Err(err) = sched.shutdown().await {
-            error!("Cancelled {:?}", err);
-        }
+        report_while(status.as_ref(), Phase::StopPending, drain(&mut sched, &enforcer)).await?;
         // info!("Cancelled");
         break;
       },
+      Some(control) = controls.recv() => match control {
+        Control::Pause => {
+            report_while(status.as_ref(), Phase::PausePending, enforcer.pause()).await?;
+            status.report(Phase::Paused)?;
+            info!("enforcement paused");
+        }
+        Control::Continue => {
+            report_while(status.as_ref(), Phase::ContinuePending, enforcer.resume()).await?;
+            status.report(Phase::Running)?;
+            info!("enforcement resumed");
+        }
+        Control::Preshutdown => {
+            let final_pass = config.triggers.contains(&Trigger::Preshutdown);
+            report_while(status.as_ref(), Phase::StopPending, async {
+                if final_pass {
+                    info!("preshutdown: final enforcement pass");
+                    enforcer.clone().cycle().await;
+                }
+                drain(&mut sched, &enforcer).await;
+            })
+            .await?;
+            break;
+        }
+        Control::Trigger(trigger) => {
+            if config.triggers.contains(&trigger) {
+                info!("{:?}: enforcing now", trigger);
+                tokio::spawn(enforcer.clone().cycle());
+            }
+        }
+        Control::User(UserEvent::EnforceNow) => {
+            info!("enforcing now on request");
+            tokio::spawn(enforcer.clone().cycle());
+        }
+        Control::User(UserEvent::ReloadConfig) => {
+            match Config::load(config_path.as_deref()) {
+                Ok(mut new_config) => {
+                    *enforcer.policy.lock().unwrap() = Arc::new(Policy::from_config(&new_config));
+                    if new_config.schedule != config.schedule {
+                        match enforce_job(&new_config.schedule, &enforcer) {
+                            Ok(job) => {
+                                if let Err(err) = sched.remove(&job_id).await {
+                                    error!("failed to remove the scheduled job: {:?}", err);
+                                }
+                                job_id = sched.add(job).await.expect("Should be able to add a job");
+                            }
+                            Err(err) => {
+                                error!("keeping schedule {}: {:#}", config.schedule, err);
+                                new_config.schedule = config.schedule.clone();
+                            }
+                        }
+                    }
+                    info!("config reloaded: {:?}", new_config);
+                    config = new_config;
+                }
+                Err(err) => error!("config reload failed, keeping the current config: {:#}", err),
+            }
+        }
+        Control::User(UserEvent::RotateLogs) => match own_logger::rotate_logs() {
+            Ok(()) => info!("log file rotated"),
+            Err(err) => error!("log rotation failed: {}", err),
+        },
+      },
     }
 }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use window_update_blocker::scm::{FakeServiceController, ServiceState, StartType};
+
+    #[derive(Parser)]
+    struct Install {
+        #[command(flatten)]
+        options: InstallOptions,
+    }
+
+    fn service_options(args: &[&str]) -> ServiceOptions {
+        let args = std::iter::once("install").chain(args.iter().copied());
+        Install::try_parse_from(args)
+            .unwrap()
+            .options
+            .service_options()
+    }
+
+    #[test]
+    fn install_options_default_to_a_plain_service() {
+        assert_eq!(
+            service_options(&[]),
+            ServiceOptions {
+                description: Some(OsString::from(SERVICE_DESCRIPTION)),
+                delayed_auto_start: Some(false),
+                failure_actions: None,
+                failure_actions_on_non_crash: Some(false),
+                sid_type: None,
+            }
+        );
+    }
+
+    #[test]
+    fn install_options_map_every_flag() {
+        let options = service_options(&[
+            "--description",
+            "blocker",
+            "--delayed-start",
+            "--restart-after",
+            "5",
+            "--restart-after",
+            "60",
+            "--reset-period",
+            "3600",
+            "--failure-actions-on-non-crash",
+            "--sid-type",
+            "restricted",
+        ]);
+        assert_eq!(
+            options,
+            ServiceOptions {
+                description: Some(OsString::from("blocker")),
+                delayed_auto_start: Some(true),
+                failure_actions: Some(FailureActions {
+                    restart_delays: vec![Duration::from_secs(5), Duration::from_secs(60)],
+                    reset_period: Some(Duration::from_secs(3600)),
+                }),
+                failure_actions_on_non_crash: Some(true),
+                sid_type: Some(SidType::Restricted),
+            }
+        );
+    }
+
+    #[test]
+    fn install_options_reject_bad_input() {
+        assert!(Install::try_parse_from(["install", "--sid-type", "everyone"]).is_err());
+        assert!(Install::try_parse_from(["install", "--restart-after", "-1"]).is_err());
+        // The password only makes sense with an account.
+        assert!(Install::try_parse_from(["install", "--password-env", "PW"]).is_err());
+        assert!(Install::try_parse_from([
+            "install",
+            "--account",
+            "svc",
+            "--password-env",
+            "PW",
+            "--password-stdin",
+        ])
+        .is_err());
+    }
+
+    /// Records every phase the executor reports, and whether wuauserv was
+    /// disabled by then.
+    struct Phases {
+        services: Arc<FakeServiceController>,
+        reported: Mutex<Vec<(Phase, bool)>>,
+    }
+
+    impl StatusSink for Phases {
+        fn report(&self, phase: Phase) -> anyhow::Result<()> {
+            let disabled = self.services.start_type("wuauserv") == Some(StartType::Disabled);
+            self.reported.lock().unwrap().push((phase, disabled));
+            Ok(())
+        }
+    }
+
+    /// An executor on a memory host where wuauserv and bits are running.
+    struct Executor {
+        services: Arc<FakeServiceController>,
+        phases: Arc<Phases>,
+        controls: mpsc::UnboundedSender<Control>,
+        token: CancellationToken,
+        task: tokio::task::JoinHandle<anyhow::Result<()>>,
+    }
+
+    /// Never fires while a test runs, passes only come from the executor.
+    const NEVER: &str = "0 0 0 1 1 *";
+
+    fn config(services: &[&str]) -> Config {
+        Config {
+            schedule: NEVER.to_owned(),
+            triggers: vec![Trigger::Logon],
+            services: services.iter().map(|s| s.to_string()).collect(),
+            ..Config::default()
+        }
+    }
+
+    impl Executor {
+        fn start(config: Config, config_path: Option<PathBuf>) -> Self {
+            let services = Arc::new(FakeServiceController::new());
+            services.insert("wuauserv", StartType::OnDemand, ServiceState::Running);
+            services.insert("bits", StartType::OnDemand, ServiceState::Running);
+            let host = Host {
+                services: services.clone(),
+                ..Host::memory()
+            };
+            let enforcer = Enforcer::new(host, &config, Snapshot::memory(), TamperLog::memory());
+            let phases = Arc::new(Phases {
+                services: services.clone(),
+                reported: Mutex::default(),
+            });
+            let (controls, rx) = mpsc::unbounded_channel();
+            let token = CancellationToken::new();
+            let status: Arc<dyn StatusSink> = phases.clone();
+            let task = tokio::spawn(serv_executor(
+                token.clone(),
+                config,
+                config_path,
+                rx,
+                status,
+                enforcer,
+            ));
+            Self {
+                services,
+                phases,
+                controls,
+                token,
+                task,
+            }
+        }
+
+        fn send(&self, control: Control) {
+            self.controls.send(control).unwrap();
+        }
+
+        fn phases(&self) -> Vec<Phase> {
+            let reported = self.phases.reported.lock().unwrap();
+            reported.iter().map(|(phase, _)| *phase).collect()
+        }
+
+        /// Waits until `phase` was reported `count` times.
+        async fn reported(&self, phase: Phase, count: usize) {
+            let done = || self.phases().iter().filter(|p| **p == phase).count() >= count;
+            assert!(eventually(done).await, "{:?}", self.phases());
+        }
+
+        fn is_disabled(&self, name: &str) -> bool {
+            self.services.start_type(name) == Some(StartType::Disabled)
+        }
+
+        /// Undoes the last pass on `name`.
+        fn restart(&self, name: &str) {
+            self.services
+                .insert(name, StartType::OnDemand, ServiceState::Running);
+        }
+
+        async fn stop(self) {
+            self.token.cancel();
+            let result = tokio::time::timeout(Duration::from_secs(5), self.task).await;
+            result.unwrap().unwrap().unwrap();
+        }
+    }
+
+    async fn eventually(f: impl Fn() -> bool) -> bool {
+        for _ in 0..250 {
+            if f() {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        false
+    }
+
+    /// Long enough for a pass that should not run to show up.
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn executor_runs_the_first_pass_before_running() {
+        let executor = Executor::start(config(&["wuauserv"]), None);
+        executor.reported(Phase::Running, 1).await;
+        let reported = executor.phases.reported.lock().unwrap().clone();
+        assert!(reported.contains(&(Phase::Running, true)), "{:?}", reported);
+        assert!(
+            !reported.contains(&(Phase::Running, false)),
+            "{:?}",
+            reported
+        );
+        assert!(!executor.is_disabled("bits"));
+        executor.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn executor_exits_when_cancelled() {
+        let executor = Executor::start(config(&["wuauserv"]), None);
+        executor.reported(Phase::Running, 1).await;
+        let controls = executor.controls.clone();
+        executor.stop().await;
+        // Nothing receives controls any more.
+        assert!(controls.send(Control::Pause).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn executor_skips_passes_while_paused() {
+        let executor = Executor::start(config(&["wuauserv"]), None);
+        executor.reported(Phase::Running, 1).await;
+
+        executor.send(Control::Pause);
+        executor.reported(Phase::Paused, 1).await;
+        executor.restart("wuauserv");
+        executor.send(Control::Trigger(Trigger::Logon));
+        executor.send(Control::User(UserEvent::EnforceNow));
+        settle().await;
+        assert!(!executor.is_disabled("wuauserv"));
+
+        // Continue runs a pass before reporting Running.
+        executor.send(Control::Continue);
+        executor.reported(Phase::Running, 2).await;
+        assert!(executor.is_disabled("wuauserv"));
+        executor.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn executor_runs_only_the_configured_triggers() {
+        let executor = Executor::start(config(&["wuauserv"]), None);
+        executor.reported(Phase::Running, 1).await;
+
+        executor.restart("wuauserv");
+        executor.send(Control::Trigger(Trigger::Unlock));
+        settle().await;
+        assert!(!executor.is_disabled("wuauserv"));
+
+        executor.send(Control::Trigger(Trigger::Logon));
+        assert!(eventually(|| executor.is_disabled("wuauserv")).await);
+        executor.stop().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn executor_reload_swaps_the_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let executor = Executor::start(config(&["wuauserv"]), Some(path.clone()));
+        executor.reported(Phase::Running, 1).await;
+
+        fs::write(
+            &path,
+            format!("schedule = {:?}\nservices = [\"bits\"]\n", NEVER),
+        )
+        .unwrap();
+        executor.restart("wuauserv");
+        executor.send(Control::User(UserEvent::ReloadConfig));
+        executor.send(Control::User(UserEvent::EnforceNow));
+        assert!(eventually(|| executor.is_disabled("bits")).await);
+        assert!(!executor.is_disabled("wuauserv"));
+
+        // A broken config keeps the current policy.
+        fs::write(&path, "services = 42\n").unwrap();
+        executor.restart("bits");
+        executor.send(Control::User(UserEvent::ReloadConfig));
+        executor.send(Control::User(UserEvent::EnforceNow));
+        assert!(eventually(|| executor.is_disabled("bits")).await);
+        executor.stop().await;
+    }
+
+    #[test]
+    fn exit_codes_do_not_overlap() {
+        let usage = Args::try_parse_from(["window_update_blocker", "--no-such-flag"]).unwrap_err();
+        assert_eq!(usage.exit_code(), 2);
+
+        assert_eq!(exit_code(&anyhow!("Invalid config file")), 1);
+        assert_eq!(exit_code(&Drifted(2).into()), EXIT_DRIFTED);
+        assert_eq!(exit_code(&anyhow::Error::from(ServiceError::NotFound)), 10);
+        // `--computer` adds context around the error.
+        let remote = anyhow::Error::from(ServiceError::Unreachable(1722)).context("on lab-01");
+        assert_eq!(exit_code(&remote), 17);
+    }
+}
@@ -1 +1,2 @@
-pub mod windows;
\ No newline at end of file
+#[cfg(windows)]
+pub mod windows;
@@ -0,0 +1,327 @@
+//! Rolling "pause updates".
+//!
+//! Windows Update stays paused until the timestamps under `UX\Settings` run
+//! out. [`PauseUpdates`] computes them from a [`Clock`], so the service can keep
+//! the pause rolled forward a fixed number of days ahead of now and the
+//! Settings app keeps showing a regular pause.
+
+use std::{cmp, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{RegistryData, RegistryValue};
+
+pub const UX_SETTINGS_KEY: &str = r#"HKLM\SOFTWARE\Microsoft\WindowsUpdate\UX\Settings"#;
+
+/// Format of the pause timestamps, as the Settings app writes them: `2024-05-01T10:00:00Z`.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Longest pause the Settings app offers.
+pub const MAX_PAUSE_DAYS: u32 = 35;
+
+/// How often the pause is rolled forward at most, in hours.
+const RENEW_INTERVAL_HOURS: i64 = 24;
+
+/// Values holding the start of the pause.
+const START_VALUES: &[&str] = &[
+    "PauseUpdatesStartTime",
+    "PauseFeatureUpdatesStartTime",
+    "PauseQualityUpdatesStartTime",
+];
+/// Values holding the end of the pause.
+const END_VALUES: &[&str] = &[
+    "PauseUpdatesExpiryTime",
+    "PauseFeatureUpdatesEndTime",
+    "PauseQualityUpdatesEndTime",
+];
+
+/// Source of the current time, replaced by a [`ManualClock`] in tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock(Mutex<DateTime<Utc>>);
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Mutex::new(now))
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Keep Windows Update paused for the next `days` days.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PauseUpdates {
+    pub days: u32,
+}
+
+impl Default for PauseUpdates {
+    fn default() -> Self {
+        Self { days: 7 }
+    }
+}
+
+/// Where a recorded pause stands relative to now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PauseState {
+    /// Ends far enough ahead.
+    Paused,
+    /// Still paused, but due to be rolled forward.
+    Expiring { until: DateTime<Utc> },
+    /// Not paused: values missing, unreadable, or already ended.
+    Lapsed,
+}
+
+impl PauseUpdates {
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_PAUSE_DAYS).contains(&self.days) {
+            return Err(anyhow!(
+                "pause_updates: days must be between 1 and {}, got {}",
+                MAX_PAUSE_DAYS,
+                self.days
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::days(self.days.into())
+    }
+
+    /// The values pausing updates from `now` until the end of the window.
+    pub fn registry_values(&self, now: DateTime<Utc>) -> Vec<RegistryValue> {
+        let start = format_timestamp(now);
+        let end = format_timestamp(now + self.window());
+        let value = |name: &str, data: &str| RegistryValue {
+            key: UX_SETTINGS_KEY.to_owned(),
+            name: name.to_owned(),
+            value: RegistryData::String(data.to_owned()),
+        };
+        START_VALUES
+            .iter()
+            .map(|name| value(name, &start))
+            .chain(END_VALUES.iter().map(|name| value(name, &end)))
+            .collect()
+    }
+
+    /// Names of every value the pause writes.
+    pub fn value_names() -> impl Iterator<Item = &'static str> {
+        START_VALUES.iter().chain(END_VALUES).copied()
+    }
+
+    /// Judges the pause recorded in `values`, looked up by name.
+    ///
+    /// The pause is due once less than `days - 1` days remain (half a day for
+    /// a one-day pause), so it is rewritten about once a day rather than on
+    /// every cycle.
+    pub fn state(
+        &self,
+        now: DateTime<Utc>,
+        values: impl Fn(&str) -> Option<RegistryData>,
+    ) -> PauseState {
+        let read = |name: &str| match values(name) {
+            Some(RegistryData::String(text)) => parse_timestamp(&text),
+            _ => None,
+        };
+        let starts: Option<Vec<_>> = START_VALUES.iter().map(|name| read(name)).collect();
+        let ends: Option<Vec<_>> = END_VALUES.iter().map(|name| read(name)).collect();
+        let (Some(starts), Some(ends)) = (starts, ends) else {
+            return PauseState::Lapsed;
+        };
+        let until = match ends.into_iter().min() {
+            Some(until) if until > now && starts.iter().all(|start| *start <= now) => until,
+            _ => return PauseState::Lapsed,
+        };
+        let renew_interval = Duration::hours(RENEW_INTERVAL_HOURS);
+        let due = self.window() - cmp::min(renew_interval, self.window() / 2);
+        if until < now + due {
+            PauseState::Expiring { until }
+        } else {
+            PauseState::Paused
+        }
+    }
+}
+
+pub fn format_timestamp(time: DateTime<Utc>) -> String {
+    time.format(TIMESTAMP_FORMAT).to_string()
+}
+
+pub fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(text, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap()
+    }
+
+    /// The values of `pause` written at `written`, looked up by name.
+    fn written(
+        pause: &PauseUpdates,
+        written: DateTime<Utc>,
+    ) -> impl Fn(&str) -> Option<RegistryData> {
+        let values = pause.registry_values(written);
+        move |name| {
+            values
+                .iter()
+                .find(|v| v.name == name)
+                .map(|v| v.value.clone())
+        }
+    }
+
+    #[test]
+    fn timestamps_use_the_settings_app_format() {
+        let time = at(2024, 5, 1, 10);
+        assert_eq!(format_timestamp(time), "2024-05-01T10:00:00Z");
+        assert_eq!(parse_timestamp("2024-05-01T10:00:00Z"), Some(time));
+        for text in [
+            "",
+            "2024-05-01",
+            "2024-05-01 10:00:00",
+            "2024-05-01T10:00:00+02:00",
+        ] {
+            assert_eq!(parse_timestamp(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn values_span_the_configured_days() {
+        let pause = PauseUpdates { days: 7 };
+        let values = pause.registry_values(at(2024, 5, 1, 10));
+        assert_eq!(values.len(), PauseUpdates::value_names().count());
+        for value in &values {
+            assert_eq!(value.key, UX_SETTINGS_KEY);
+            let expected = if START_VALUES.contains(&value.name.as_str()) {
+                "2024-05-01T10:00:00Z"
+            } else {
+                "2024-05-08T10:00:00Z"
+            };
+            assert_eq!(
+                value.value,
+                RegistryData::String(expected.to_owned()),
+                "{}",
+                value.name
+            );
+        }
+
+        // Ends roll over months and years.
+        let values = PauseUpdates { days: 35 }.registry_values(at(2024, 12, 20, 0));
+        assert_eq!(
+            values.last().unwrap().value,
+            RegistryData::String("2025-01-24T00:00:00Z".to_owned())
+        );
+    }
+
+    #[test]
+    fn validate_bounds_the_days() {
+        for days in [1, 7, MAX_PAUSE_DAYS] {
+            PauseUpdates { days }.validate().unwrap();
+        }
+        for days in [0, MAX_PAUSE_DAYS + 1] {
+            assert!(PauseUpdates { days }.validate().is_err(), "{}", days);
+        }
+    }
+
+    #[test]
+    fn renewal_is_due_after_about_a_day() {
+        let pause = PauseUpdates { days: 7 };
+        let start = at(2024, 5, 1, 10);
+        let values = written(&pause, start);
+
+        assert_eq!(pause.state(start, &values), PauseState::Paused);
+        assert_eq!(
+            pause.state(start + Duration::hours(23), &values),
+            PauseState::Paused
+        );
+        assert_eq!(
+            pause.state(start + Duration::hours(24), &values),
+            PauseState::Paused
+        );
+        assert_eq!(
+            pause.state(start + Duration::hours(25), &values),
+            PauseState::Expiring {
+                until: at(2024, 5, 8, 10)
+            }
+        );
+        assert_eq!(
+            pause.state(start + Duration::days(6), &values),
+            PauseState::Expiring {
+                until: at(2024, 5, 8, 10)
+            }
+        );
+        assert_eq!(
+            pause.state(start + Duration::days(7), &values),
+            PauseState::Lapsed
+        );
+    }
+
+    #[test]
+    fn a_one_day_pause_is_renewed_after_half_a_day() {
+        let pause = PauseUpdates { days: 1 };
+        let start = at(2024, 5, 1, 10);
+        let values = written(&pause, start);
+
+        assert_eq!(
+            pause.state(start + Duration::hours(11), &values),
+            PauseState::Paused
+        );
+        assert!(matches!(
+            pause.state(start + Duration::hours(13), &values),
+            PauseState::Expiring { .. }
+        ));
+    }
+
+    #[test]
+    fn missing_future_or_malformed_values_are_lapsed() {
+        let pause = PauseUpdates { days: 7 };
+        let start = at(2024, 5, 1, 10);
+        assert_eq!(pause.state(start, |_| None), PauseState::Lapsed);
+
+        // A pause that starts later, e.g. after the clock was set back.
+        let values = written(&pause, start);
+        assert_eq!(
+            pause.state(start - Duration::hours(1), &values),
+            PauseState::Lapsed
+        );
+
+        let malformed = |name: &str| match name {
+            "PauseUpdatesExpiryTime" => Some(RegistryData::Dword(1)),
+            _ => values(name),
+        };
+        assert_eq!(pause.state(start, malformed), PauseState::Lapsed);
+    }
+}
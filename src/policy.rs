@@ -0,0 +1,511 @@
+//! The ordered set of rules evaluated on every enforcement cycle.
+
+use std::{fmt, sync::Arc};
+
+use own_logger::*;
+
+use crate::{
+    config::{Config, Mode, UPDATE_SERVICES, UPDATE_TASKS},
+    pause::{Clock, SystemClock},
+    service::SERVICE_NAME,
+    rules::{
+        Compliance, Host, ProcessAbsent, RegistryListLength, RegistryValue, Rule,
+        ScheduledTaskDisabled, ScheduledTaskEnabled, ServiceDacl, ServiceDisabled,
+        ServiceStartType, UpdatesPaused,
+    },
+    snapshot::Snapshot,
+};
+
+pub struct Policy {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleStatus {
+    /// Nothing to do.
+    Compliant,
+    /// The target does not exist.
+    Absent,
+    /// The rule had drifted and was applied successfully.
+    Fixed { observed: String },
+    Failed(String),
+}
+
+impl RuleStatus {
+    /// `Err` for [`RuleStatus::Failed`], for callers that only need to know whether it worked.
+    pub fn into_result(self) -> anyhow::Result<()> {
+        match self {
+            RuleStatus::Failed(e) => Err(anyhow::anyhow!(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleReport {
+    pub rule: String,
+    pub status: RuleStatus,
+    /// What the check found when the rule was not compliant at the start of the cycle.
+    pub drift: Option<Drift>,
+    /// What applying the rule did, when the rule tells, e.g. "already disabled"
+    /// for a service that stopped on its own between the check and the fix.
+    pub outcome: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    pub expected: String,
+    pub observed: String,
+}
+
+impl RuleReport {
+    pub fn new(rule: String, status: RuleStatus) -> Self {
+        Self {
+            rule,
+            status,
+            drift: None,
+            outcome: None,
+        }
+    }
+}
+
+/// Outcome of one enforcement cycle, one entry per rule in policy order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CycleReport {
+    pub rules: Vec<RuleReport>,
+}
+
+impl CycleReport {
+    pub fn compliant(&self) -> usize {
+        self.count(|s| matches!(s, RuleStatus::Compliant))
+    }
+
+    pub fn absent(&self) -> usize {
+        self.count(|s| matches!(s, RuleStatus::Absent))
+    }
+
+    pub fn fixed(&self) -> usize {
+        self.count(|s| matches!(s, RuleStatus::Fixed { .. }))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|s| matches!(s, RuleStatus::Failed(_)))
+    }
+
+    fn count(&self, f: impl Fn(&RuleStatus) -> bool) -> usize {
+        self.rules.iter().filter(|r| f(&r.status)).count()
+    }
+}
+
+impl fmt::Display for CycleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} compliant, {} fixed, {} not present, {} failed",
+            self.compliant(),
+            self.fixed(),
+            self.absent(),
+            self.failed()
+        )
+    }
+}
+
+/// Compliance of one rule, without changing anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleCheck {
+    pub rule: String,
+    pub compliance: Result<Compliance, String>,
+}
+
+/// Outcome of [`Policy::check`], one entry per rule in policy order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    pub rules: Vec<RuleCheck>,
+}
+
+impl CheckReport {
+    /// Rules that drifted or could not be checked.
+    pub fn drifted(&self) -> usize {
+        self.rules
+            .iter()
+            .filter(|r| !matches!(&r.compliance, Ok(c) if c.is_compliant()))
+            .count()
+    }
+
+    pub fn is_compliant(&self) -> bool {
+        self.drifted() == 0
+    }
+}
+
+impl Policy {
+    pub fn new(rules: Vec<Box<dyn Rule>>) -> Self {
+        Self { rules }
+    }
+
+    /// Services first, so nothing restarts what the later rules clean up.
+    ///
+    /// Outside [`Mode::Block`] the [`UPDATE_SERVICES`] and [`UPDATE_TASKS`]
+    /// are set back to their defaults instead of disabled, and the processes
+    /// are left alone, see [`Mode::keeps_updates_running`].
+    pub fn from_config(config: &Config) -> Self {
+        Self::from_config_with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Like [`Policy::from_config`], with time-based rules reading `clock`.
+    pub fn from_config_with_clock(config: &Config, clock: Arc<dyn Clock>) -> Self {
+        let keep_running = config.mode.keeps_updates_running();
+        let mut rules: Vec<Box<dyn Rule>> = vec![];
+        for name in &config.services {
+            if keep_running && UPDATE_SERVICES.iter().any(|(x, _)| x.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            rules.push(Box::new(ServiceDisabled::new(name)));
+        }
+        if keep_running {
+            for (name, start_type) in UPDATE_SERVICES {
+                rules.push(Box::new(ServiceStartType::new(name, *start_type)));
+            }
+        }
+        let mut values = config.update_policy.registry_values();
+        values.extend(config.drivers.registry_values());
+        if config.mode == Mode::Defer {
+            values.extend(config.defer_updates.registry_values());
+        }
+        for value in config.registry.iter().chain(&values) {
+            rules.push(Box::new(RegistryValue::new(value)));
+        }
+        for list in config.drivers.numbered_lists() {
+            rules.push(Box::new(RegistryListLength::new(&list)));
+        }
+        if config.mode == Mode::Pause {
+            let pause = config.pause_updates.clone().unwrap_or_default();
+            rules.push(Box::new(UpdatesPaused::new(&pause, clock)));
+        }
+        for path in &config.tasks {
+            if keep_running && UPDATE_TASKS.iter().any(|x| x.eq_ignore_ascii_case(path)) {
+                continue;
+            }
+            rules.push(Box::new(ScheduledTaskDisabled::new(path)));
+        }
+        if keep_running {
+            for path in UPDATE_TASKS {
+                rules.push(Box::new(ScheduledTaskEnabled::new(path)));
+            }
+        }
+        if !keep_running {
+            for name in &config.processes {
+                rules.push(Box::new(ProcessAbsent::new(name)));
+            }
+        }
+        if let Some(permissions) = &config.service_permissions {
+            rules.push(Box::new(ServiceDacl::new(SERVICE_NAME, permissions)));
+        }
+        Self::new(rules)
+    }
+
+    pub fn rules(&self) -> &[Box<dyn Rule>] {
+        &self.rules
+    }
+
+    /// Keeps only the rules for which `f` returns `true`.
+    pub fn retain(&mut self, mut f: impl FnMut(&dyn Rule) -> bool) {
+        self.rules.retain(|rule| f(rule.as_ref()));
+    }
+
+    /// Checks every rule and applies the ones that drifted.
+    ///
+    /// The original state of a target is recorded in `snapshot`, and saved,
+    /// before it is changed for the first time.
+    pub fn evaluate(&self, host: &Host, snapshot: &mut Snapshot) -> CycleReport {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let report = Self::enforce(rule.as_ref(), host, snapshot);
+                match &report.status {
+                    RuleStatus::Compliant => debug!("{}: compliant", rule.id()),
+                    RuleStatus::Absent => debug!("{}: not present", rule.id()),
+                    RuleStatus::Fixed { observed } => match &report.outcome {
+                        Some(outcome) => {
+                            info!("{}: fixed (was {}): {}", rule.id(), observed, outcome)
+                        }
+                        None => info!("{}: fixed (was {})", rule.id(), observed),
+                    },
+                    RuleStatus::Failed(e) => error!("{}: failed: {}", rule.id(), e),
+                }
+                report
+            })
+            .collect();
+        CycleReport { rules }
+    }
+
+    /// Checks every rule without applying anything.
+    pub fn check(&self, host: &Host) -> CheckReport {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| RuleCheck {
+                rule: rule.id(),
+                compliance: rule.check(host).map_err(|e| format!("{:#}", e)),
+            })
+            .collect();
+        CheckReport { rules }
+    }
+
+    /// Reverts every rule to the state recorded in `snapshot`, in reverse order.
+    pub fn revert(&self, host: &Host, snapshot: &Snapshot) -> CycleReport {
+        let rules = self
+            .rules
+            .iter()
+            .rev()
+            .map(|rule| {
+                let status = match rule.revert(host, snapshot) {
+                    Ok(()) => RuleStatus::Compliant,
+                    Err(e) => RuleStatus::Failed(format!("{:#}", e)),
+                };
+                RuleReport::new(rule.id(), status)
+            })
+            .collect();
+        CycleReport { rules }
+    }
+
+    fn enforce(rule: &dyn Rule, host: &Host, snapshot: &mut Snapshot) -> RuleReport {
+        let drift = match rule.check(host) {
+            Ok(Compliance::Compliant) => return RuleReport::new(rule.id(), RuleStatus::Compliant),
+            Ok(Compliance::Absent) => return RuleReport::new(rule.id(), RuleStatus::Absent),
+            Ok(Compliance::Expiring { observed }) => {
+                // A renewal, not a drift: it is reported as fixed but never as tampering.
+                let (status, outcome) = Self::fix(rule, host, snapshot, &observed);
+                return RuleReport {
+                    outcome,
+                    ..RuleReport::new(rule.id(), status)
+                };
+            }
+            Ok(Compliance::Drifted { expected, observed }) => Drift { expected, observed },
+            Err(e) => {
+                return RuleReport::new(rule.id(), RuleStatus::Failed(format!("check: {:#}", e)))
+            }
+        };
+        let (status, outcome) = Self::fix(rule, host, snapshot, &drift.observed);
+        RuleReport {
+            rule: rule.id(),
+            status,
+            drift: Some(drift),
+            outcome,
+        }
+    }
+
+    /// Applies `rule` and checks that it took, along with what the rule said it did.
+    fn fix(
+        rule: &dyn Rule,
+        host: &Host,
+        snapshot: &mut Snapshot,
+        observed: &str,
+    ) -> (RuleStatus, Option<String>) {
+        // Never change a target whose original state could not be saved.
+        match rule.capture(host, snapshot) {
+            Ok(true) => {
+                if let Err(e) = snapshot.save() {
+                    return (RuleStatus::Failed(format!("snapshot: {:#}", e)), None);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => return (RuleStatus::Failed(format!("snapshot: {:#}", e)), None),
+        }
+        let outcome = match rule.apply(host) {
+            Ok(outcome) => outcome,
+            Err(e) => return (RuleStatus::Failed(format!("apply: {:#}", e)), None),
+        };
+        let status = match rule.check(host) {
+            Ok(Compliance::Expiring { observed }) => {
+                RuleStatus::Failed(format!("still due for renewal ({})", observed))
+            }
+            Ok(c) if c.is_compliant() => RuleStatus::Fixed {
+                observed: observed.to_owned(),
+            },
+            Ok(drift) => RuleStatus::Failed(format!("still {}", drift)),
+            Err(e) => RuleStatus::Failed(format!("check: {:#}", e)),
+        };
+        (status, outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{RegistryData, BLOCK_PROCESSES},
+        pause::ManualClock,
+        process::FakeProcessTable,
+        registry::Hive,
+        scm::{FakeServiceController, ServiceState, StartType},
+        tasks::{FakeTaskScheduler, TaskScheduler, TaskState},
+        update_policy::DriverPolicy,
+    };
+
+    /// A host where Windows Update is enabled and running.
+    fn running_host(config: &Config) -> (Host, Arc<FakeServiceController>, Arc<FakeTaskScheduler>) {
+        let services = Arc::new(FakeServiceController::new());
+        for name in &config.services {
+            services.insert(name, StartType::AutoStart, ServiceState::Running);
+        }
+        let tasks = Arc::new(FakeTaskScheduler::new());
+        for path in &config.tasks {
+            tasks.insert(path, TaskState::Enabled);
+        }
+        let processes = Arc::new(FakeProcessTable::new());
+        for (pid, name) in (100..).zip(BLOCK_PROCESSES) {
+            processes.spawn(pid, &format!("{}.exe", name));
+        }
+        let host = Host {
+            services: services.clone(),
+            tasks: tasks.clone(),
+            processes,
+            ..Host::memory()
+        };
+        (host, services, tasks)
+    }
+
+    fn policy(config: &Config) -> Policy {
+        let clock = Arc::new(ManualClock::new(chrono::Utc::now()));
+        Policy::from_config_with_clock(config, clock)
+    }
+
+    #[test]
+    fn block_cycle_fixes_every_drift() {
+        let config = Config::default();
+        let (host, services, tasks) = running_host(&config);
+        let policy = policy(&config);
+
+        let before = policy.check(&host);
+        assert!(!before.is_compliant());
+        assert_eq!(before.drifted(), policy.rules().len());
+
+        let mut snapshot = Snapshot::memory();
+        let report = policy.evaluate(&host, &mut snapshot);
+        assert_eq!(report.failed(), 0, "{:#?}", report);
+        assert_eq!(report.fixed(), policy.rules().len());
+        assert!(report.rules.iter().all(|r| r.drift.is_some()));
+        for rule in &report.rules {
+            let expected = rule.rule.starts_with("service:").then(|| "disabled now".to_owned());
+            assert_eq!(rule.outcome, expected, "{}", rule.rule);
+        }
+
+        assert!(policy.check(&host).is_compliant());
+        for name in &config.services {
+            assert_eq!(services.start_type(name), Some(StartType::Disabled));
+            assert_eq!(services.state(name), Some(ServiceState::Stopped));
+        }
+        for path in &config.tasks {
+            assert_eq!(tasks.state(path).unwrap(), Some(TaskState::Disabled));
+        }
+        for name in &config.processes {
+            assert_eq!(host.processes.find(name).unwrap(), Vec::<u32>::new());
+        }
+
+        // Nothing left to do on the next cycle.
+        let report = policy.evaluate(&host, &mut snapshot);
+        assert_eq!(report.compliant(), policy.rules().len());
+    }
+
+    #[test]
+    fn revert_restores_the_snapshot() {
+        let config = Config::default();
+        let (host, services, tasks) = running_host(&config);
+        let policy = policy(&config);
+        let mut snapshot = Snapshot::memory();
+        policy.evaluate(&host, &mut snapshot);
+
+        let report = policy.revert(&host, &snapshot);
+        assert_eq!(report.failed(), 0, "{:#?}", report);
+        for name in &config.services {
+            assert_eq!(services.start_type(name), Some(StartType::AutoStart));
+        }
+        for path in &config.tasks {
+            assert_eq!(tasks.state(path).unwrap(), Some(TaskState::Enabled));
+        }
+        for value in &config.registry {
+            let (hive, key) = value.split_key().unwrap();
+            assert_eq!(host.registry.get(hive, key, &value.name).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn defer_cycle_sets_the_update_services_back_to_their_defaults() {
+        let config = Config {
+            mode: Mode::Defer,
+            ..Config::default()
+        };
+        let (host, services, tasks) = running_host(&config);
+        services.insert("wuauserv", StartType::Disabled, ServiceState::Stopped);
+        services.insert("UsoSvc", StartType::OnDemand, ServiceState::Running);
+        for path in UPDATE_TASKS {
+            tasks.insert(path, TaskState::Disabled);
+        }
+        let policy = policy(&config);
+        assert!(!policy.check(&host).is_compliant());
+
+        let report = policy.evaluate(&host, &mut Snapshot::memory());
+        assert_eq!(report.failed(), 0, "{:#?}", report);
+        assert!(policy.check(&host).is_compliant());
+        for (name, start_type) in UPDATE_SERVICES {
+            assert_eq!(services.start_type(name), Some(*start_type));
+        }
+        // Only the start type changes, a running service keeps running.
+        assert_eq!(services.state("UsoSvc"), Some(ServiceState::Running));
+        assert_eq!(
+            services.start_type("WaaSMedicSvc"),
+            Some(StartType::Disabled)
+        );
+        for path in UPDATE_TASKS {
+            assert_eq!(tasks.state(path).unwrap(), Some(TaskState::Enabled));
+        }
+        // The update processes keep running.
+        for name in &config.processes {
+            assert_eq!(host.processes.find(name).unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn shrinking_a_deny_list_deletes_the_dropped_entries() {
+        let deny = |ids: &[&str]| Config {
+            drivers: DriverPolicy {
+                deny_device_ids: ids.iter().map(|id| id.to_string()).collect(),
+                ..DriverPolicy::default()
+            },
+            ..Config::default()
+        };
+        let config = deny(&["PCI\\VEN_10DE", "USB\\VID_046D", "HID\\VID_045E"]);
+        let (host, _, _) = running_host(&config);
+        let mut snapshot = Snapshot::memory();
+        policy(&config).evaluate(&host, &mut snapshot);
+
+        let key = r#"SOFTWARE\Policies\Microsoft\Windows\DeviceInstall\Restrictions\DenyDeviceIDs"#;
+        let entry = |name: &str| host.registry.get(Hive::LocalMachine, key, name).unwrap();
+        assert_eq!(
+            entry("3"),
+            Some(RegistryData::String("HID\\VID_045E".to_owned()))
+        );
+
+        let config = deny(&["USB\\VID_046D"]);
+        let policy = policy(&config);
+        assert!(!policy.check(&host).is_compliant());
+        let report = policy.evaluate(&host, &mut snapshot);
+        assert_eq!(report.failed(), 0, "{:#?}", report);
+        assert!(policy.check(&host).is_compliant());
+        assert_eq!(
+            entry("1"),
+            Some(RegistryData::String("USB\\VID_046D".to_owned()))
+        );
+        assert_eq!(entry("2"), None);
+        assert_eq!(entry("3"), None);
+
+        // The entries were missing before the first cycle and stay that way.
+        let report = policy.revert(&host, &snapshot);
+        assert_eq!(report.failed(), 0, "{:#?}", report);
+        assert_eq!(
+            host.registry.value_names(Hive::LocalMachine, key).unwrap(),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use std::{collections::BTreeMap, io, sync::Mutex};
+
+use super::*;
+
+/// In-memory process list keyed by pid.
+#[derive(Debug, Default)]
+pub struct FakeProcessTable {
+    processes: Mutex<BTreeMap<u32, String>>,
+}
+
+impl FakeProcessTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&self, pid: u32, image: &str) {
+        self.processes.lock().unwrap().insert(pid, image.to_owned());
+    }
+}
+
+impl ProcessTable for FakeProcessTable {
+    fn find(&self, name: &str) -> io::Result<Vec<u32>> {
+        let name = image_name(name);
+        Ok(self
+            .processes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, image)| image_name(image) == name)
+            .map(|(pid, _)| *pid)
+            .collect())
+    }
+
+    fn kill(&self, pid: u32) -> io::Result<()> {
+        match self.processes.lock().unwrap().remove(&pid) {
+            Some(_) => Ok(()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no process {}", pid),
+            )),
+        }
+    }
+}
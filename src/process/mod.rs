@@ -0,0 +1,29 @@
+//! Process lookup and termination.
+//!
+//! [`ProcessTable`] is backed by a Toolhelp snapshot on Windows
+//! ([`WindowsProcessTable`]) and by [`FakeProcessTable`] in tests.
+
+use std::io;
+
+mod fake;
+#[cfg(windows)]
+mod windows;
+
+pub use fake::FakeProcessTable;
+#[cfg(windows)]
+pub use self::windows::WindowsProcessTable;
+
+pub trait ProcessTable {
+    /// Ids of the running processes whose image is `name`, with or without `.exe`.
+    fn find(&self, name: &str) -> io::Result<Vec<u32>>;
+    fn kill(&self, pid: u32) -> io::Result<()>;
+}
+
+/// Image name without a trailing `.exe`, lowercased for comparison.
+pub fn image_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    match name.strip_suffix(".exe") {
+        Some(stem) => stem.to_owned(),
+        None => name,
+    }
+}
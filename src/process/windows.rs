@@ -0,0 +1,63 @@
+use std::{io, mem};
+
+use winapi::shared::minwindef::FALSE;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::processthreadsapi::{OpenProcess, TerminateProcess};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W,
+    TH32CS_SNAPPROCESS,
+};
+use winapi::um::winnt::PROCESS_TERMINATE;
+
+use super::*;
+
+/// [`ProcessTable`] using a Toolhelp process snapshot.
+#[derive(Debug, Default, Clone)]
+pub struct WindowsProcessTable;
+
+impl ProcessTable for WindowsProcessTable {
+    fn find(&self, name: &str) -> io::Result<Vec<u32>> {
+        let name = image_name(name);
+        let mut pids = vec![];
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0);
+            if snapshot == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            let mut entry: PROCESSENTRY32W = mem::zeroed();
+            entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+
+            let mut ok = Process32FirstW(snapshot, &mut entry);
+            while ok != FALSE {
+                let len = entry
+                    .szExeFile
+                    .iter()
+                    .position(|c| *c == 0)
+                    .unwrap_or(entry.szExeFile.len());
+                let image = String::from_utf16_lossy(&entry.szExeFile[..len]);
+                if image_name(&image) == name {
+                    pids.push(entry.th32ProcessID);
+                }
+                ok = Process32NextW(snapshot, &mut entry);
+            }
+            CloseHandle(snapshot);
+        }
+        Ok(pids)
+    }
+
+    fn kill(&self, pid: u32) -> io::Result<()> {
+        unsafe {
+            let handle = OpenProcess(PROCESS_TERMINATE, FALSE, pid);
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let ok = TerminateProcess(handle, 1);
+            let err = io::Error::last_os_error();
+            CloseHandle(handle);
+            if ok == FALSE {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    sync::Mutex,
+};
+
+use super::*;
+
+/// In-memory registry. Keys and value names are case-insensitive like the
+/// real one, and `deny` makes writes below a key fail with access denied.
+#[derive(Debug, Default)]
+pub struct MemoryHive {
+    values: Mutex<BTreeMap<(Hive, String, String), RegistryData>>,
+    denied: Mutex<HashSet<(Hive, String)>>,
+    writes: Mutex<usize>,
+}
+
+impl MemoryHive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seeds a value without counting it as a write.
+    pub fn insert(&self, hive: Hive, key: &str, name: &str, value: RegistryData) {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(Self::path(hive, key, name), value);
+    }
+
+    pub fn deny(&self, hive: Hive, key: &str) {
+        self.denied
+            .lock()
+            .unwrap()
+            .insert((hive, key.to_ascii_lowercase()));
+    }
+
+    /// Number of successful `set`/`delete` calls so far.
+    pub fn writes(&self) -> usize {
+        *self.writes.lock().unwrap()
+    }
+
+    fn path(hive: Hive, key: &str, name: &str) -> (Hive, String, String) {
+        (hive, key.to_ascii_lowercase(), name.to_ascii_lowercase())
+    }
+
+    fn check_write(&self, hive: Hive, key: &str) -> io::Result<()> {
+        if self
+            .denied
+            .lock()
+            .unwrap()
+            .contains(&(hive, key.to_ascii_lowercase()))
+        {
+            return Err(io::Error::from_raw_os_error(crate::scm::ERROR_ACCESS_DENIED));
+        }
+        Ok(())
+    }
+}
+
+impl RegistryStore for MemoryHive {
+    fn get(&self, hive: Hive, key: &str, name: &str) -> io::Result<Option<RegistryData>> {
+        Ok(self
+            .values
+            .lock()
+            .unwrap()
+            .get(&Self::path(hive, key, name))
+            .cloned())
+    }
+
+    fn set(&self, hive: Hive, key: &str, name: &str, value: &RegistryData) -> io::Result<()> {
+        self.check_write(hive, key)?;
+        self.values
+            .lock()
+            .unwrap()
+            .insert(Self::path(hive, key, name), value.clone());
+        *self.writes.lock().unwrap() += 1;
+        Ok(())
+    }
+
+    fn delete(&self, hive: Hive, key: &str, name: &str) -> io::Result<()> {
+        self.check_write(hive, key)?;
+        self.values
+            .lock()
+            .unwrap()
+            .remove(&Self::path(hive, key, name));
+        *self.writes.lock().unwrap() += 1;
+        Ok(())
+    }
+
+    fn value_names(&self, hive: Hive, key: &str) -> io::Result<Vec<String>> {
+        let key = key.to_ascii_lowercase();
+        Ok(self
+            .values
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(h, k, _)| *h == hive && *k == key)
+            .map(|(_, _, name)| name.clone())
+            .collect())
+    }
+}
@@ -0,0 +1,99 @@
+//! Native registry enforcement.
+//!
+//! Values are read, compared and written through [`RegistryStore`], backed by
+//! `winreg` on Windows ([`WindowsRegistry`]) and by [`MemoryHive`] in tests.
+
+use std::{fmt, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{RegistryData, RegistryValue};
+
+mod fake;
+#[cfg(windows)]
+mod windows;
+
+pub use fake::MemoryHive;
+#[cfg(windows)]
+pub use self::windows::WindowsRegistry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Hive {
+    LocalMachine,
+    CurrentUser,
+}
+
+impl Hive {
+    pub fn parse(root: &str) -> Option<Hive> {
+        match root.to_ascii_uppercase().as_str() {
+            "HKLM" | "HKEY_LOCAL_MACHINE" => Some(Hive::LocalMachine),
+            "HKCU" | "HKEY_CURRENT_USER" => Some(Hive::CurrentUser),
+            _ => None,
+        }
+    }
+
+    /// Splits `HKLM\SOFTWARE\...` into the hive and the key below it.
+    pub fn split_path(path: &str) -> Option<(Hive, &str)> {
+        let (root, key) = path.split_once('\\')?;
+        let hive = Hive::parse(root)?;
+        if key.is_empty() {
+            return None;
+        }
+        Some((hive, key))
+    }
+}
+
+impl fmt::Display for Hive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hive::LocalMachine => write!(f, "HKLM"),
+            Hive::CurrentUser => write!(f, "HKCU"),
+        }
+    }
+}
+
+pub trait RegistryStore {
+    /// Reads a value, `None` when the key or the value does not exist.
+    fn get(&self, hive: Hive, key: &str, name: &str) -> io::Result<Option<RegistryData>>;
+    /// Writes a value, creating the key when needed.
+    fn set(&self, hive: Hive, key: &str, name: &str, value: &RegistryData) -> io::Result<()>;
+    /// Deletes a value; deleting a missing value is not an error.
+    fn delete(&self, hive: Hive, key: &str, name: &str) -> io::Result<()>;
+    /// Names of the values of `key`, empty when the key does not exist.
+    fn value_names(&self, hive: Hive, key: &str) -> io::Result<Vec<String>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueOutcome {
+    /// The value already had the wanted data.
+    Unchanged,
+    /// The value was written, `previous` is what it held before.
+    Written { previous: Option<RegistryData> },
+    Failed(String),
+}
+
+/// Writes `value` only when the current data differs.
+pub fn apply(store: &dyn RegistryStore, value: &RegistryValue) -> ValueOutcome {
+    let run = || -> io::Result<ValueOutcome> {
+        let (hive, key) = split(value)?;
+        let previous = store.get(hive, key, &value.name)?;
+        if previous.as_ref() == Some(&value.value) {
+            return Ok(ValueOutcome::Unchanged);
+        }
+        store.set(hive, key, &value.name, &value.value)?;
+        Ok(ValueOutcome::Written { previous })
+    };
+    match run() {
+        Ok(outcome) => outcome,
+        Err(e) => ValueOutcome::Failed(e.to_string()),
+    }
+}
+
+fn split(value: &RegistryValue) -> io::Result<(Hive, &str)> {
+    value.split_key().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported registry key {:?}", value.key),
+        )
+    })
+}
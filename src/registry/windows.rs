@@ -0,0 +1,77 @@
+use std::io;
+
+use winreg::{
+    enums::*,
+    types::FromRegValue,
+    RegKey,
+};
+
+use super::*;
+
+/// [`RegistryStore`] backed by the real registry, always in the 64-bit view.
+#[derive(Debug, Default, Clone)]
+pub struct WindowsRegistry;
+
+impl WindowsRegistry {
+    fn root(hive: Hive) -> RegKey {
+        match hive {
+            Hive::LocalMachine => RegKey::predef(HKEY_LOCAL_MACHINE),
+            Hive::CurrentUser => RegKey::predef(HKEY_CURRENT_USER),
+        }
+    }
+}
+
+impl RegistryStore for WindowsRegistry {
+    fn get(&self, hive: Hive, key: &str, name: &str) -> io::Result<Option<RegistryData>> {
+        let subkey = match Self::root(hive).open_subkey_with_flags(key, KEY_QUERY_VALUE | KEY_WOW64_64KEY) {
+            Ok(k) => k,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let raw = match subkey.get_raw_value(name) {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match raw.vtype {
+            REG_DWORD => Ok(Some(RegistryData::Dword(u32::from_reg_value(&raw)?))),
+            REG_SZ | REG_EXPAND_SZ => Ok(Some(RegistryData::String(String::from_reg_value(&raw)?))),
+            vtype => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported registry type {:?}", vtype),
+            )),
+        }
+    }
+
+    fn set(&self, hive: Hive, key: &str, name: &str, value: &RegistryData) -> io::Result<()> {
+        let (subkey, _) = Self::root(hive).create_subkey_with_flags(key, KEY_SET_VALUE | KEY_WOW64_64KEY)?;
+        match value {
+            RegistryData::Dword(v) => subkey.set_value(name, v),
+            RegistryData::String(v) => subkey.set_value(name, v),
+        }
+    }
+
+    fn delete(&self, hive: Hive, key: &str, name: &str) -> io::Result<()> {
+        let subkey = match Self::root(hive).open_subkey_with_flags(key, KEY_SET_VALUE | KEY_WOW64_64KEY) {
+            Ok(k) => k,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match subkey.delete_value(name) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    }
+
+    fn value_names(&self, hive: Hive, key: &str) -> io::Result<Vec<String>> {
+        let subkey = match Self::root(hive).open_subkey_with_flags(key, KEY_QUERY_VALUE | KEY_WOW64_64KEY) {
+            Ok(k) => k,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        subkey
+            .enum_values()
+            .map(|value| value.map(|(name, _)| name))
+            .collect()
+    }
+}
@@ -0,0 +1,110 @@
+//! Declarative blocking rules.
+//!
+//! Every blocking action is a [`Rule`] that can tell whether the system is
+//! compliant, bring it into compliance, and undo its change. Rules only talk
+//! to the system through the backends bundled in a [`Host`].
+
+use std::{fmt, sync::Arc};
+
+use crate::{
+    process::ProcessTable, registry::RegistryStore, scm::ServiceController, snapshot::Snapshot,
+    tasks::TaskScheduler,
+};
+
+mod pause;
+mod process;
+mod registry;
+mod service;
+mod service_dacl;
+mod task;
+
+pub use pause::UpdatesPaused;
+pub use process::ProcessAbsent;
+pub use registry::{RegistryListLength, RegistryValue};
+pub use service::{ServiceDisabled, ServiceStartType};
+pub use service_dacl::ServiceDacl;
+pub use task::{ScheduledTaskDisabled, ScheduledTaskEnabled};
+
+/// The system backends rules act on.
+#[derive(Clone)]
+pub struct Host {
+    pub services: Arc<dyn ServiceController + Send + Sync>,
+    pub registry: Arc<dyn RegistryStore + Send + Sync>,
+    pub tasks: Arc<dyn TaskScheduler + Send + Sync>,
+    pub processes: Arc<dyn ProcessTable + Send + Sync>,
+}
+
+impl Host {
+    /// The local machine.
+    #[cfg(windows)]
+    pub fn windows() -> Self {
+        Self {
+            services: Arc::new(crate::scm::WindowsServiceController::local()),
+            registry: Arc::new(crate::registry::WindowsRegistry),
+            tasks: Arc::new(crate::tasks::WindowsTaskScheduler),
+            processes: Arc::new(crate::process::WindowsProcessTable),
+        }
+    }
+
+    /// Empty in-memory backends, every target reports as not present.
+    pub fn memory() -> Self {
+        Self {
+            services: Arc::new(crate::scm::FakeServiceController::new()),
+            registry: Arc::new(crate::registry::MemoryHive::new()),
+            tasks: Arc::new(crate::tasks::FakeTaskScheduler::new()),
+            processes: Arc::new(crate::process::FakeProcessTable::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compliance {
+    Compliant,
+    /// The target does not exist, so there is nothing to enforce.
+    Absent,
+    Drifted { expected: String, observed: String },
+    /// Compliant for now, but the target only holds for a limited time and is
+    /// due to be renewed. Renewing it is not a drift.
+    Expiring { observed: String },
+}
+
+impl Compliance {
+    pub fn is_compliant(&self) -> bool {
+        matches!(
+            self,
+            Compliance::Compliant | Compliance::Absent | Compliance::Expiring { .. }
+        )
+    }
+}
+
+impl fmt::Display for Compliance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compliance::Compliant => write!(f, "compliant"),
+            Compliance::Absent => write!(f, "not present"),
+            Compliance::Drifted { expected, observed } => {
+                write!(f, "drifted: expected {}, found {}", expected, observed)
+            }
+            Compliance::Expiring { observed } => write!(f, "compliant, due for renewal ({})", observed),
+        }
+    }
+}
+
+pub trait Rule: Send + Sync {
+    /// Stable identifier, e.g. `service:wuauserv`.
+    fn id(&self) -> String;
+    /// Is the system compliant with this rule?
+    fn check(&self, host: &Host) -> anyhow::Result<Compliance>;
+    /// Records the original state of the target in `snapshot` unless it is already there.
+    /// Returns `true` when something new was recorded.
+    fn capture(&self, _host: &Host, _snapshot: &mut Snapshot) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+    /// Makes the system compliant. Returns what was done when the rule can tell
+    /// more than that, e.g. that a service was already disabled.
+    fn apply(&self, host: &Host) -> anyhow::Result<Option<String>>;
+    /// Puts the target back into the state [`Rule::capture`] recorded in
+    /// `snapshot`; a target that was never captured was never changed and is
+    /// left alone.
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> anyhow::Result<()>;
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+
+use super::*;
+use crate::{
+    config::RegistryData,
+    pause::{self, Clock, PauseState, PauseUpdates},
+    registry::{self, Hive, ValueOutcome},
+};
+
+/// Windows Update is paused for the next [`PauseUpdates::days`] days.
+///
+/// A pause that is still running but due to be rolled forward is
+/// [`Compliance::Expiring`], so the daily renewal is not reported as tampering.
+pub struct UpdatesPaused {
+    pub pause: PauseUpdates,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl UpdatesPaused {
+    pub fn new(pause: &PauseUpdates, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            pause: pause.clone(),
+            clock,
+        }
+    }
+
+    /// [`pause::UX_SETTINGS_KEY`] below `HKLM`.
+    fn key() -> &'static str {
+        pause::UX_SETTINGS_KEY
+            .strip_prefix("HKLM\\")
+            .expect("UX_SETTINGS_KEY is below HKLM")
+    }
+}
+
+impl Rule for UpdatesPaused {
+    fn id(&self) -> String {
+        format!("pause:{}", pause::UX_SETTINGS_KEY)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        let mut values: BTreeMap<&str, RegistryData> = BTreeMap::new();
+        for name in PauseUpdates::value_names() {
+            if let Some(data) = host.registry.get(Hive::LocalMachine, Self::key(), name)? {
+                values.insert(name, data);
+            }
+        }
+        let state = self
+            .pause
+            .state(self.clock.now(), |name| values.get(name).cloned());
+        Ok(match state {
+            PauseState::Paused => Compliance::Compliant,
+            PauseState::Expiring { until } => Compliance::Expiring {
+                observed: format!("paused until {}", pause::format_timestamp(until)),
+            },
+            PauseState::Lapsed => Compliance::Drifted {
+                expected: format!("paused for {} days", self.pause.days),
+                observed: match values.get("PauseUpdatesExpiryTime") {
+                    Some(RegistryData::String(until)) => format!("paused until {}", until),
+                    Some(data) => format!("{:?}", data),
+                    None => "not paused".to_owned(),
+                },
+            },
+        })
+    }
+
+    fn capture(&self, host: &Host, snapshot: &mut Snapshot) -> Result<bool> {
+        let mut recorded = false;
+        for value in self.pause.registry_values(self.clock.now()) {
+            recorded |= RegistryValue::new(&value).capture(host, snapshot)?;
+        }
+        Ok(recorded)
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        for value in self.pause.registry_values(self.clock.now()) {
+            if let ValueOutcome::Failed(e) = registry::apply(host.registry.as_ref(), &value) {
+                return Err(anyhow!("{}\\{}: {}", value.key, value.name, e));
+            }
+        }
+        Ok(None)
+    }
+
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> Result<()> {
+        for value in self.pause.registry_values(self.clock.now()) {
+            RegistryValue::new(&value).revert(host, snapshot)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::*;
+    use crate::{
+        pause::ManualClock,
+        policy::{Policy, RuleStatus},
+        registry::{MemoryHive, RegistryStore},
+    };
+
+    fn setup() -> (Arc<ManualClock>, Arc<MemoryHive>, Host, UpdatesPaused) {
+        let clock = Arc::new(ManualClock::new(
+            Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap(),
+        ));
+        let hive = Arc::new(MemoryHive::new());
+        let host = Host {
+            registry: hive.clone(),
+            ..Host::memory()
+        };
+        let rule = UpdatesPaused::new(&PauseUpdates { days: 7 }, clock.clone());
+        (clock, hive, host, rule)
+    }
+
+    fn expiry(hive: &MemoryHive) -> Option<RegistryData> {
+        hive.get(
+            Hive::LocalMachine,
+            UpdatesPaused::key(),
+            "PauseUpdatesExpiryTime",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn apply_pauses_from_now() {
+        let (_, hive, host, rule) = setup();
+        assert_eq!(
+            rule.check(&host).unwrap(),
+            Compliance::Drifted {
+                expected: "paused for 7 days".to_owned(),
+                observed: "not paused".to_owned(),
+            }
+        );
+
+        rule.apply(&host).unwrap();
+        assert_eq!(rule.check(&host).unwrap(), Compliance::Compliant);
+        assert_eq!(
+            expiry(&hive),
+            Some(RegistryData::String("2024-05-08T10:00:00Z".to_owned()))
+        );
+        assert_eq!(hive.writes(), PauseUpdates::value_names().count());
+    }
+
+    #[test]
+    fn renews_only_after_about_a_day() {
+        let (clock, hive, host, rule) = setup();
+        let policy = Policy::new(vec![Box::new(UpdatesPaused::new(
+            &rule.pause,
+            clock.clone(),
+        ))]);
+        let mut snapshot = Snapshot::memory();
+        policy.evaluate(&host, &mut snapshot);
+        let writes = hive.writes();
+
+        clock.advance(Duration::hours(23));
+        let report = policy.evaluate(&host, &mut snapshot);
+        assert_eq!(report.rules[0].status, RuleStatus::Compliant);
+        assert_eq!(hive.writes(), writes);
+
+        clock.advance(Duration::hours(2));
+        assert_eq!(
+            rule.check(&host).unwrap(),
+            Compliance::Expiring {
+                observed: "paused until 2024-05-08T10:00:00Z".to_owned(),
+            }
+        );
+        let report = policy.evaluate(&host, &mut snapshot);
+        assert!(matches!(report.rules[0].status, RuleStatus::Fixed { .. }));
+        // A renewal is not drift.
+        assert_eq!(report.rules[0].drift, None);
+        assert_eq!(
+            expiry(&hive),
+            Some(RegistryData::String("2024-05-09T11:00:00Z".to_owned()))
+        );
+        assert_eq!(rule.check(&host).unwrap(), Compliance::Compliant);
+    }
+
+    #[test]
+    fn a_lapsed_pause_is_drift() {
+        let (clock, _, host, rule) = setup();
+        rule.apply(&host).unwrap();
+        clock.advance(Duration::days(8));
+        assert_eq!(
+            rule.check(&host).unwrap(),
+            Compliance::Drifted {
+                expected: "paused for 7 days".to_owned(),
+                observed: "paused until 2024-05-08T10:00:00Z".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn revert_restores_the_values_before_the_first_pause() {
+        let (clock, hive, host, rule) = setup();
+        let mut snapshot = Snapshot::memory();
+        assert!(rule.capture(&host, &mut snapshot).unwrap());
+        rule.apply(&host).unwrap();
+
+        // Renewals later on do not change what is restored.
+        clock.advance(Duration::days(2));
+        assert!(!rule.capture(&host, &mut snapshot).unwrap());
+        rule.apply(&host).unwrap();
+
+        rule.revert(&host, &snapshot).unwrap();
+        for name in PauseUpdates::value_names() {
+            assert_eq!(
+                hive.get(Hive::LocalMachine, UpdatesPaused::key(), name)
+                    .unwrap(),
+                None,
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+
+use super::*;
+
+/// No process with the given image name is running.
+#[derive(Debug, Clone)]
+pub struct ProcessAbsent {
+    pub name: String,
+}
+
+impl ProcessAbsent {
+    /// Prefix of the [`Rule::id`] of every process rule.
+    pub const ID_PREFIX: &'static str = "process:";
+
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl Rule for ProcessAbsent {
+    fn id(&self) -> String {
+        format!("{}{}", Self::ID_PREFIX, self.name)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        let pids = host.processes.find(&self.name)?;
+        if pids.is_empty() {
+            return Ok(Compliance::Compliant);
+        }
+        Ok(Compliance::Drifted {
+            expected: "not running".to_owned(),
+            observed: format!("running as pid {:?}", pids),
+        })
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        let errors: Vec<String> = host
+            .processes
+            .find(&self.name)?
+            .into_iter()
+            .filter_map(|pid| {
+                host.processes
+                    .kill(pid)
+                    .err()
+                    .map(|e| format!("pid {}: {}", pid, e))
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(None)
+        } else {
+            Err(anyhow!(errors.join(", ")))
+        }
+    }
+
+    /// A killed process cannot be brought back; Windows restarts it on demand.
+    fn revert(&self, _host: &Host, _snapshot: &Snapshot) -> Result<()> {
+        Ok(())
+    }
+}
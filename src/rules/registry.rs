@@ -0,0 +1,366 @@
+use anyhow::{anyhow, Result};
+
+use super::*;
+use crate::{
+    config,
+    registry::{self, Hive, ValueOutcome},
+    snapshot::{RegistrySnapshot, Snapshot},
+    update_policy::NumberedList,
+};
+
+/// A registry value holds the configured data.
+#[derive(Debug, Clone)]
+pub struct RegistryValue {
+    pub value: config::RegistryValue,
+}
+
+impl RegistryValue {
+    pub fn new(value: &config::RegistryValue) -> Self {
+        Self {
+            value: value.clone(),
+        }
+    }
+}
+
+impl Rule for RegistryValue {
+    fn id(&self) -> String {
+        format!("registry:{}\\{}", self.value.key, self.value.name)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        let (hive, key) = self
+            .value
+            .split_key()
+            .ok_or_else(|| anyhow!("unsupported registry key {:?}", self.value.key))?;
+        let observed = host.registry.get(hive, key, &self.value.name)?;
+        if observed.as_ref() == Some(&self.value.value) {
+            return Ok(Compliance::Compliant);
+        }
+        Ok(Compliance::Drifted {
+            expected: format!("{:?}", self.value.value),
+            observed: match observed {
+                Some(v) => format!("{:?}", v),
+                None => "missing".to_owned(),
+            },
+        })
+    }
+
+    fn capture(&self, host: &Host, snapshot: &mut Snapshot) -> Result<bool> {
+        let (hive, key) = self
+            .value
+            .split_key()
+            .ok_or_else(|| anyhow!("unsupported registry key {:?}", self.value.key))?;
+        let id = Snapshot::registry_key(hive, key, &self.value.name);
+        if snapshot.registry.contains_key(&id) {
+            return Ok(false);
+        }
+        let value = host.registry.get(hive, key, &self.value.name)?;
+        snapshot.registry.insert(
+            id,
+            RegistrySnapshot {
+                hive,
+                key: key.to_owned(),
+                name: self.value.name.clone(),
+                value,
+            },
+        );
+        Ok(true)
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        match registry::apply(host.registry.as_ref(), &self.value) {
+            ValueOutcome::Failed(e) => Err(anyhow!(e)),
+            _ => Ok(None),
+        }
+    }
+
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> Result<()> {
+        let (hive, key) = self
+            .value
+            .split_key()
+            .ok_or_else(|| anyhow!("unsupported registry key {:?}", self.value.key))?;
+        snapshot
+            .restore_value(host, hive, key, &self.value.name)
+            .into_result()
+    }
+}
+
+/// A numbered list key (values `1`, `2`, ...) holds no entries past the
+/// end of the configured list, so a list that shrank stops matching the
+/// entries it dropped. The entries themselves are [`RegistryValue`] rules.
+#[derive(Debug, Clone)]
+pub struct RegistryListLength {
+    pub list: NumberedList,
+}
+
+impl RegistryListLength {
+    pub fn new(list: &NumberedList) -> Self {
+        Self { list: list.clone() }
+    }
+
+    fn split_key(&self) -> Result<(Hive, &str)> {
+        Hive::split_path(&self.list.key)
+            .ok_or_else(|| anyhow!("unsupported registry key {:?}", self.list.key))
+    }
+
+    fn is_stale(&self, name: &str) -> bool {
+        name.parse::<usize>().is_ok_and(|i| i > self.list.len)
+    }
+
+    /// Names of the entries past the end of the list, in order.
+    fn stale(&self, host: &Host) -> Result<Vec<String>> {
+        let (hive, key) = self.split_key()?;
+        let mut names: Vec<String> = host
+            .registry
+            .value_names(hive, key)?
+            .into_iter()
+            .filter(|name| self.is_stale(name))
+            .collect();
+        names.sort_by_key(|name| name.parse::<usize>().unwrap_or_default());
+        Ok(names)
+    }
+}
+
+impl Rule for RegistryListLength {
+    fn id(&self) -> String {
+        format!("registry-list:{}", self.list.key)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        let stale = self.stale(host)?;
+        if stale.is_empty() {
+            return Ok(Compliance::Compliant);
+        }
+        Ok(Compliance::Drifted {
+            expected: format!("{} entries", self.list.len),
+            observed: format!("also {}", stale.join(", ")),
+        })
+    }
+
+    fn capture(&self, host: &Host, snapshot: &mut Snapshot) -> Result<bool> {
+        let (hive, key) = self.split_key()?;
+        let mut captured = false;
+        for name in self.stale(host)? {
+            let id = Snapshot::registry_key(hive, key, &name);
+            if snapshot.registry.contains_key(&id) {
+                continue;
+            }
+            let value = host.registry.get(hive, key, &name)?;
+            snapshot.registry.insert(
+                id,
+                RegistrySnapshot {
+                    hive,
+                    key: key.to_owned(),
+                    name,
+                    value,
+                },
+            );
+            captured = true;
+        }
+        Ok(captured)
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        let (hive, key) = self.split_key()?;
+        for name in self.stale(host)? {
+            host.registry.delete(hive, key, &name)?;
+        }
+        Ok(None)
+    }
+
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> Result<()> {
+        let (hive, key) = self.split_key()?;
+        for value in snapshot.registry.values() {
+            if value.hive == hive
+                && value.key.eq_ignore_ascii_case(key)
+                && self.is_stale(&value.name)
+            {
+                snapshot
+                    .restore_value(host, hive, &value.key, &value.name)
+                    .into_result()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::RegistryData,
+        registry::{Hive, MemoryHive, RegistryStore},
+    };
+
+    const KEY: &str = r#"SYSTEM\ControlSet001\Services\WaaSMedicSvc"#;
+
+    fn rule() -> RegistryValue {
+        RegistryValue::new(&config::RegistryValue {
+            key: format!(r#"HKLM\{}"#, KEY),
+            name: "Start".to_owned(),
+            value: RegistryData::Dword(4),
+        })
+    }
+
+    fn host(hive: &Arc<MemoryHive>) -> Host {
+        Host {
+            registry: hive.clone(),
+            ..Host::memory()
+        }
+    }
+
+    #[test]
+    fn check_reports_drift() {
+        let hive = Arc::new(MemoryHive::new());
+        let host = host(&hive);
+        assert_eq!(
+            rule().check(&host).unwrap(),
+            Compliance::Drifted {
+                expected: "Dword(4)".to_owned(),
+                observed: "missing".to_owned(),
+            }
+        );
+
+        hive.insert(Hive::LocalMachine, KEY, "Start", RegistryData::Dword(3));
+        assert_eq!(
+            rule().check(&host).unwrap(),
+            Compliance::Drifted {
+                expected: "Dword(4)".to_owned(),
+                observed: "Dword(3)".to_owned(),
+            }
+        );
+
+        // Keys and value names are case-insensitive.
+        hive.insert(
+            Hive::LocalMachine,
+            &KEY.to_uppercase(),
+            "START",
+            RegistryData::Dword(4),
+        );
+        assert_eq!(rule().check(&host).unwrap(), Compliance::Compliant);
+    }
+
+    #[test]
+    fn apply_writes_only_when_the_value_differs() {
+        let hive = Arc::new(MemoryHive::new());
+        let host = host(&hive);
+        hive.insert(Hive::LocalMachine, KEY, "Start", RegistryData::Dword(3));
+
+        rule().apply(&host).unwrap();
+        assert_eq!(hive.writes(), 1);
+        assert_eq!(
+            hive.get(Hive::LocalMachine, KEY, "Start").unwrap(),
+            Some(RegistryData::Dword(4))
+        );
+
+        rule().apply(&host).unwrap();
+        assert_eq!(hive.writes(), 1);
+    }
+
+    #[test]
+    fn apply_reports_a_denied_write() {
+        let hive = Arc::new(MemoryHive::new());
+        hive.deny(Hive::LocalMachine, KEY);
+        assert!(rule().apply(&host(&hive)).is_err());
+        assert_eq!(hive.writes(), 0);
+    }
+
+    #[test]
+    fn capture_and_revert_restore_the_previous_value() {
+        let hive = Arc::new(MemoryHive::new());
+        let host = host(&hive);
+        hive.insert(Hive::LocalMachine, KEY, "Start", RegistryData::Dword(3));
+        let mut snapshot = Snapshot::memory();
+
+        assert!(rule().capture(&host, &mut snapshot).unwrap());
+        rule().apply(&host).unwrap();
+        // Only the first capture records anything.
+        assert!(!rule().capture(&host, &mut snapshot).unwrap());
+
+        rule().revert(&host, &snapshot).unwrap();
+        assert_eq!(
+            hive.get(Hive::LocalMachine, KEY, "Start").unwrap(),
+            Some(RegistryData::Dword(3))
+        );
+    }
+
+    #[test]
+    fn capture_and_revert_remove_a_value_that_was_missing() {
+        let hive = Arc::new(MemoryHive::new());
+        let host = host(&hive);
+        let mut snapshot = Snapshot::memory();
+
+        assert!(rule().capture(&host, &mut snapshot).unwrap());
+        rule().apply(&host).unwrap();
+        rule().revert(&host, &snapshot).unwrap();
+        assert_eq!(hive.get(Hive::LocalMachine, KEY, "Start").unwrap(), None);
+    }
+
+    #[test]
+    fn revert_without_a_capture_leaves_the_value_alone() {
+        let hive = Arc::new(MemoryHive::new());
+        let host = host(&hive);
+        hive.insert(Hive::LocalMachine, KEY, "Start", RegistryData::Dword(4));
+
+        rule().revert(&host, &Snapshot::memory()).unwrap();
+        assert_eq!(
+            hive.get(Hive::LocalMachine, KEY, "Start").unwrap(),
+            Some(RegistryData::Dword(4))
+        );
+        assert_eq!(hive.writes(), 0);
+    }
+
+    const LIST: &str =
+        r#"SOFTWARE\Policies\Microsoft\Windows\DeviceInstall\Restrictions\DenyDeviceIDs"#;
+
+    fn list(len: usize) -> RegistryListLength {
+        RegistryListLength::new(&NumberedList {
+            key: format!(r#"HKLM\{}"#, LIST),
+            len,
+        })
+    }
+
+    #[test]
+    fn list_length_deletes_the_entries_past_the_end() {
+        let hive = Arc::new(MemoryHive::new());
+        let host = host(&hive);
+        for i in 1..=11 {
+            let data = RegistryData::String(format!("PCI\\VEN_{}", i));
+            hive.insert(Hive::LocalMachine, LIST, &i.to_string(), data);
+        }
+        hive.insert(Hive::LocalMachine, LIST, "Other", RegistryData::Dword(1));
+
+        assert_eq!(
+            list(9).check(&host).unwrap(),
+            Compliance::Drifted {
+                expected: "9 entries".to_owned(),
+                observed: "also 10, 11".to_owned(),
+            }
+        );
+        let mut snapshot = Snapshot::memory();
+        assert!(list(9).capture(&host, &mut snapshot).unwrap());
+        list(9).apply(&host).unwrap();
+        assert_eq!(list(9).check(&host).unwrap(), Compliance::Compliant);
+        assert_eq!(hive.writes(), 2);
+        assert!(hive.get(Hive::LocalMachine, LIST, "9").unwrap().is_some());
+        assert_eq!(hive.get(Hive::LocalMachine, LIST, "10").unwrap(), None);
+        // Values that are not numbered are not part of the list.
+        assert!(hive
+            .get(Hive::LocalMachine, LIST, "Other")
+            .unwrap()
+            .is_some());
+
+        list(9).revert(&host, &snapshot).unwrap();
+        assert_eq!(
+            hive.get(Hive::LocalMachine, LIST, "11").unwrap(),
+            Some(RegistryData::String("PCI\\VEN_11".to_owned()))
+        );
+    }
+
+    #[test]
+    fn list_length_of_a_missing_key_is_compliant() {
+        let host = host(&Arc::new(MemoryHive::new()));
+        assert_eq!(list(1).check(&host).unwrap(), Compliance::Compliant);
+        assert!(!list(1).capture(&host, &mut Snapshot::memory()).unwrap());
+    }
+}
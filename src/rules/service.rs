@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Result};
+
+use super::*;
+use crate::{
+    kill_update::{self, KillOutcome},
+    scm::{is_not_found, ServiceState, StartType},
+    snapshot::{ServiceSnapshot, Snapshot},
+};
+
+/// The service is stopped and its start type is Disabled.
+///
+/// A service that is not installed is [`Compliance::Absent`].
+#[derive(Debug, Clone)]
+pub struct ServiceDisabled {
+    pub name: String,
+}
+
+impl ServiceDisabled {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl Rule for ServiceDisabled {
+    fn id(&self) -> String {
+        format!("service:{}", self.name)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        let config = match host.services.query_config(&self.name) {
+            Ok(config) => config,
+            Err(e) if is_not_found(&e) => return Ok(Compliance::Absent),
+            Err(e) => return Err(e.into()),
+        };
+        let status = host.services.query_status(&self.name)?;
+        if config.start_type == StartType::Disabled && status.current_state == ServiceState::Stopped
+        {
+            return Ok(Compliance::Compliant);
+        }
+        Ok(Compliance::Drifted {
+            expected: format!("{:?}/{:?}", StartType::Disabled, ServiceState::Stopped),
+            observed: format!("{:?}/{:?}", config.start_type, status.current_state),
+        })
+    }
+
+    fn capture(&self, host: &Host, snapshot: &mut Snapshot) -> Result<bool> {
+        capture(host, &self.name, snapshot)
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        match kill_update::kill_service(host.services.as_ref(), &self.name) {
+            KillOutcome::Failed(e) => Err(anyhow!(e)),
+            outcome => Ok(Some(outcome.to_string())),
+        }
+    }
+
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> Result<()> {
+        revert(host, &self.name, snapshot)
+    }
+}
+
+/// The service has the given start type, e.g. the default one of an update
+/// service that [`ServiceDisabled`] may have disabled before.
+///
+/// Whether it runs is left to Windows, which starts the update services on
+/// demand. A service that is not installed is [`Compliance::Absent`].
+#[derive(Debug, Clone)]
+pub struct ServiceStartType {
+    pub name: String,
+    pub start_type: StartType,
+}
+
+impl ServiceStartType {
+    pub fn new(name: &str, start_type: StartType) -> Self {
+        Self {
+            name: name.to_owned(),
+            start_type,
+        }
+    }
+}
+
+impl Rule for ServiceStartType {
+    fn id(&self) -> String {
+        format!("service:{}", self.name)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        let config = match host.services.query_config(&self.name) {
+            Ok(config) => config,
+            Err(e) if is_not_found(&e) => return Ok(Compliance::Absent),
+            Err(e) => return Err(e.into()),
+        };
+        if config.start_type == self.start_type {
+            return Ok(Compliance::Compliant);
+        }
+        Ok(Compliance::Drifted {
+            expected: format!("{:?}", self.start_type),
+            observed: format!("{:?}", config.start_type),
+        })
+    }
+
+    fn capture(&self, host: &Host, snapshot: &mut Snapshot) -> Result<bool> {
+        capture(host, &self.name, snapshot)
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        kill_update::set_start_type(host.services.as_ref(), &self.name, self.start_type)?;
+        Ok(None)
+    }
+
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> Result<()> {
+        revert(host, &self.name, snapshot)
+    }
+}
+
+/// Records the start type and command line of `name`, unless already recorded.
+fn capture(host: &Host, name: &str, snapshot: &mut Snapshot) -> Result<bool> {
+    let key = name.to_ascii_lowercase();
+    if snapshot.services.contains_key(&key) {
+        return Ok(false);
+    }
+    let config = match host.services.query_config(name) {
+        Ok(config) => config,
+        Err(e) if is_not_found(&e) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    snapshot.services.insert(
+        key,
+        ServiceSnapshot {
+            name: name.to_owned(),
+            start_type: config.start_type,
+            image_path: config.executable_path,
+        },
+    );
+    Ok(true)
+}
+
+/// Puts back the start type and command line [`capture`] recorded for `name`.
+fn revert(host: &Host, name: &str, snapshot: &Snapshot) -> Result<()> {
+    snapshot.restore_service(host, name).into_result()
+}
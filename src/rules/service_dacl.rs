@@ -0,0 +1,213 @@
+use anyhow::{Context, Result};
+
+use super::*;
+use crate::{
+    config::ServicePermissions,
+    scm::{is_not_found, security},
+};
+
+/// The DACL of a service grants what [`ServicePermissions`] asks for.
+///
+/// Without `harden` only the operators ACE is added and every other ACE is
+/// left alone; with it the whole DACL is replaced by
+/// [`security::hardened_dacl`]. Without operators that is the DACL Windows
+/// gives a new service, so hardening then only removes the ACEs added to it.
+/// A service that is not installed is [`Compliance::Absent`].
+#[derive(Debug, Clone)]
+pub struct ServiceDacl {
+    pub name: String,
+    pub permissions: ServicePermissions,
+}
+
+impl ServiceDacl {
+    pub fn new(name: &str, permissions: &ServicePermissions) -> Self {
+        Self {
+            name: name.to_owned(),
+            permissions: permissions.clone(),
+        }
+    }
+
+    /// The DACL the service should have, given its `current` one.
+    fn desired(&self, host: &Host, current: &str) -> Result<String> {
+        let operators = match &self.permissions.operators {
+            Some(account) => Some(
+                host.services
+                    .lookup_account(account)
+                    .with_context(|| format!("Failed to look up account {:?}", account))?,
+            ),
+            None => None,
+        };
+        if self.permissions.harden {
+            return Ok(security::hardened_dacl(operators.as_deref()));
+        }
+        Ok(match operators {
+            Some(trustee) => security::with_ace(current, &security::operators_ace(&trustee, true)),
+            None => current.to_owned(),
+        })
+    }
+}
+
+impl Rule for ServiceDacl {
+    fn id(&self) -> String {
+        format!("service-dacl:{}", self.name)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        let current = match host.services.query_security(&self.name) {
+            Ok(current) => current,
+            Err(e) if is_not_found(&e) => return Ok(Compliance::Absent),
+            Err(e) => return Err(e.into()),
+        };
+        let desired = self.desired(host, &current)?;
+        if security::dacl_aces(&current) == security::dacl_aces(&desired) {
+            return Ok(Compliance::Compliant);
+        }
+        Ok(Compliance::Drifted {
+            expected: desired,
+            observed: current,
+        })
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        let current = host.services.query_security(&self.name)?;
+        let desired = self.desired(host, &current)?;
+        host.services.set_security(&self.name, &desired)?;
+        Ok(None)
+    }
+
+    /// Puts back the DACL Windows gives a new service; the DACL is not captured.
+    fn revert(&self, host: &Host, _snapshot: &Snapshot) -> Result<()> {
+        match host.services.set_security(&self.name, &security::hardened_dacl(None)) {
+            Err(e) if is_not_found(&e) => Ok(()),
+            r => r.map_err(Into::into),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scm::{
+        FakeOp, FakeServiceController, ServiceController, ServiceState, StartType,
+        ERROR_ACCESS_DENIED,
+    };
+
+    const NAME: &str = "WindowsUpdateBlocker.rs";
+    const OPERATORS: &str = "Lab Operators";
+    const OPERATORS_SID: &str = "S-1-5-21-1-2-3-1001";
+    /// An ACE someone added to let everyone stop the service.
+    const EXTRA_ACE: &str = "(A;;RPWP;;;WD)";
+
+    fn setup() -> (Arc<FakeServiceController>, Host) {
+        let services = Arc::new(FakeServiceController::new());
+        services.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        services.add_account(OPERATORS, OPERATORS_SID);
+        let host = Host {
+            services: services.clone(),
+            ..Host::memory()
+        };
+        (services, host)
+    }
+
+    fn rule(operators: Option<&str>, harden: bool) -> ServiceDacl {
+        ServiceDacl::new(
+            NAME,
+            &ServicePermissions {
+                operators: operators.map(str::to_owned),
+                harden,
+            },
+        )
+    }
+
+    /// Checks, applies and checks again, returning the DACL it left.
+    fn enforce(rule: &ServiceDacl, host: &Host) -> String {
+        assert!(matches!(
+            rule.check(host).unwrap(),
+            Compliance::Drifted { .. }
+        ));
+        rule.apply(host).unwrap();
+        assert_eq!(rule.check(host).unwrap(), Compliance::Compliant);
+        host.services.query_security(NAME).unwrap()
+    }
+
+    #[test]
+    fn operators_are_added_and_other_aces_kept() {
+        let (services, host) = setup();
+        let current = security::with_ace(&security::hardened_dacl(None), EXTRA_ACE);
+        services.set_security(NAME, &current).unwrap();
+
+        let dacl = enforce(&rule(Some(OPERATORS), false), &host);
+        assert_eq!(
+            dacl,
+            format!(
+                "{}{}",
+                current,
+                security::operators_ace(OPERATORS_SID, true)
+            )
+        );
+        // Applying again changes nothing.
+        rule(Some(OPERATORS), false).apply(&host).unwrap();
+        assert_eq!(services.query_security(NAME).unwrap(), dacl);
+    }
+
+    #[test]
+    fn harden_without_operators_only_removes_added_aces() {
+        let (services, host) = setup();
+        assert_eq!(
+            rule(None, true).check(&host).unwrap(),
+            Compliance::Compliant
+        );
+
+        let current = security::with_ace(&security::hardened_dacl(None), EXTRA_ACE);
+        services.set_security(NAME, &current).unwrap();
+        assert_eq!(
+            enforce(&rule(None, true), &host),
+            security::hardened_dacl(None)
+        );
+    }
+
+    #[test]
+    fn harden_takes_stopping_away_from_operators() {
+        let (_, host) = setup();
+        enforce(&rule(Some(OPERATORS), false), &host);
+        assert_eq!(
+            enforce(&rule(Some(OPERATORS), true), &host),
+            security::hardened_dacl(Some(OPERATORS_SID))
+        );
+    }
+
+    #[test]
+    fn an_unknown_account_fails_the_check() {
+        let (_, host) = setup();
+        let err = rule(Some("Nobody"), false).check(&host).unwrap_err();
+        assert!(format!("{:#}", err).contains("\"Nobody\""), "{:#}", err);
+    }
+
+    #[test]
+    fn a_denied_write_fails_the_apply() {
+        let (services, host) = setup();
+        services.fail_next(FakeOp::SetSecurity, NAME, ERROR_ACCESS_DENIED);
+        assert!(rule(Some(OPERATORS), false).apply(&host).is_err());
+        assert_eq!(
+            services.query_security(NAME).unwrap(),
+            security::hardened_dacl(None)
+        );
+    }
+
+    #[test]
+    fn a_missing_service_is_absent_and_revert_resets_the_dacl() {
+        let (services, host) = setup();
+        let missing = ServiceDacl::new("missing", &ServicePermissions::default());
+        assert_eq!(missing.check(&host).unwrap(), Compliance::Absent);
+        missing.revert(&host, &Snapshot::memory()).unwrap();
+
+        enforce(&rule(Some(OPERATORS), true), &host);
+        rule(Some(OPERATORS), true)
+            .revert(&host, &Snapshot::memory())
+            .unwrap();
+        assert_eq!(
+            services.query_security(NAME).unwrap(),
+            security::hardened_dacl(None)
+        );
+    }
+}
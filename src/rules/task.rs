@@ -0,0 +1,116 @@
+use anyhow::Result;
+
+use super::*;
+use crate::{
+    snapshot::{Snapshot, TaskSnapshot},
+    tasks::TaskState,
+};
+
+/// A scheduled task is disabled. A missing task is [`Compliance::Absent`].
+#[derive(Debug, Clone)]
+pub struct ScheduledTaskDisabled {
+    pub path: String,
+}
+
+impl ScheduledTaskDisabled {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl Rule for ScheduledTaskDisabled {
+    fn id(&self) -> String {
+        format!("task:{}", self.path)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        check(host, &self.path, TaskState::Disabled)
+    }
+
+    fn capture(&self, host: &Host, snapshot: &mut Snapshot) -> Result<bool> {
+        capture(host, &self.path, snapshot)
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        host.tasks.set_enabled(&self.path, false)?;
+        Ok(None)
+    }
+
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> Result<()> {
+        revert(host, &self.path, snapshot)
+    }
+}
+
+/// A scheduled task is enabled, e.g. an update task that
+/// [`ScheduledTaskDisabled`] may have disabled before. A missing task is
+/// [`Compliance::Absent`].
+#[derive(Debug, Clone)]
+pub struct ScheduledTaskEnabled {
+    pub path: String,
+}
+
+impl ScheduledTaskEnabled {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl Rule for ScheduledTaskEnabled {
+    fn id(&self) -> String {
+        format!("task:{}", self.path)
+    }
+
+    fn check(&self, host: &Host) -> Result<Compliance> {
+        check(host, &self.path, TaskState::Enabled)
+    }
+
+    fn capture(&self, host: &Host, snapshot: &mut Snapshot) -> Result<bool> {
+        capture(host, &self.path, snapshot)
+    }
+
+    fn apply(&self, host: &Host) -> Result<Option<String>> {
+        host.tasks.set_enabled(&self.path, true)?;
+        Ok(None)
+    }
+
+    fn revert(&self, host: &Host, snapshot: &Snapshot) -> Result<()> {
+        revert(host, &self.path, snapshot)
+    }
+}
+
+fn check(host: &Host, path: &str, expected: TaskState) -> Result<Compliance> {
+    match host.tasks.state(path)? {
+        None => Ok(Compliance::Absent),
+        Some(state) if state == expected => Ok(Compliance::Compliant),
+        Some(state) => Ok(Compliance::Drifted {
+            expected: format!("{:?}", expected),
+            observed: format!("{:?}", state),
+        }),
+    }
+}
+
+/// Records the state of the task at `path`, unless already recorded.
+fn capture(host: &Host, path: &str, snapshot: &mut Snapshot) -> Result<bool> {
+    let key = path.to_ascii_lowercase();
+    if snapshot.tasks.contains_key(&key) {
+        return Ok(false);
+    }
+    let state = host.tasks.state(path)?;
+    snapshot.tasks.insert(
+        key,
+        TaskSnapshot {
+            path: path.to_owned(),
+            state,
+        },
+    );
+    Ok(true)
+}
+
+/// Puts back the state [`capture`] recorded for the task at `path`.
+fn revert(host: &Host, path: &str, snapshot: &Snapshot) -> Result<()> {
+    snapshot.restore_task(host, path).into_result()
+}
@@ -0,0 +1,142 @@
+use std::{fmt, io};
+
+use super::*;
+
+/// Failure of a service management operation, keeping the Win32 error code.
+#[derive(Debug)]
+pub enum ServiceError {
+    /// `ERROR_SERVICE_DOES_NOT_EXIST`
+    NotFound,
+    /// `ERROR_ACCESS_DENIED`, usually a missing elevation.
+    AccessDenied,
+    /// `ERROR_SERVICE_EXISTS`
+    AlreadyExists,
+    /// `ERROR_SERVICE_REQUEST_TIMEOUT`, or the service did not reach the expected state in time.
+    Timeout,
+    /// `ERROR_SERVICE_MARKED_FOR_DELETE`, the service goes away once every handle is closed.
+    MarkedForDelete,
+    /// The remote computer could not be reached: `RPC_S_SERVER_UNAVAILABLE`,
+    /// `ERROR_BAD_NETPATH` or `ERROR_INVALID_COMPUTERNAME`.
+    Unreachable(i32),
+    /// Any other Win32 error code.
+    Os(i32),
+    /// An error without a Win32 code, e.g. an invalid argument.
+    Other(io::Error),
+}
+
+impl ServiceError {
+    /// The Win32 error code, when there is one.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            ServiceError::NotFound => Some(ERROR_SERVICE_DOES_NOT_EXIST),
+            ServiceError::AccessDenied => Some(ERROR_ACCESS_DENIED),
+            ServiceError::AlreadyExists => Some(ERROR_SERVICE_EXISTS),
+            ServiceError::Timeout => Some(ERROR_SERVICE_REQUEST_TIMEOUT),
+            ServiceError::MarkedForDelete => Some(ERROR_SERVICE_MARKED_FOR_DELETE),
+            ServiceError::Unreachable(code) => Some(*code),
+            ServiceError::Os(code) => Some(*code),
+            ServiceError::Other(e) => e.raw_os_error(),
+        }
+    }
+
+    /// Process exit code reported by the CLI for this error.
+    ///
+    /// The codes start at 10, clear of the generic failure (1), clap's usage
+    /// errors (2) and `status` drift (3).
+    ///
+    /// | code | error             |
+    /// |------|-------------------|
+    /// | 10   | `NotFound`        |
+    /// | 11   | `AccessDenied`    |
+    /// | 12   | `AlreadyExists`   |
+    /// | 13   | `Timeout`         |
+    /// | 14   | `MarkedForDelete` |
+    /// | 15   | `Os`              |
+    /// | 16   | `Other`           |
+    /// | 17   | `Unreachable`     |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ServiceError::NotFound => 10,
+            ServiceError::AccessDenied => 11,
+            ServiceError::AlreadyExists => 12,
+            ServiceError::Timeout => 13,
+            ServiceError::MarkedForDelete => 14,
+            ServiceError::Os(_) => 15,
+            ServiceError::Other(_) => 16,
+            ServiceError::Unreachable(_) => 17,
+        }
+    }
+}
+
+impl From<io::Error> for ServiceError {
+    fn from(err: io::Error) -> Self {
+        match err.raw_os_error() {
+            Some(ERROR_SERVICE_DOES_NOT_EXIST) => ServiceError::NotFound,
+            Some(ERROR_ACCESS_DENIED) => ServiceError::AccessDenied,
+            Some(ERROR_SERVICE_EXISTS) => ServiceError::AlreadyExists,
+            Some(ERROR_SERVICE_REQUEST_TIMEOUT) => ServiceError::Timeout,
+            Some(ERROR_SERVICE_MARKED_FOR_DELETE) => ServiceError::MarkedForDelete,
+            Some(
+                code @ (RPC_S_SERVER_UNAVAILABLE | ERROR_BAD_NETPATH | ERROR_INVALID_COMPUTERNAME),
+            ) => ServiceError::Unreachable(code),
+            Some(code) => ServiceError::Os(code),
+            None => ServiceError::Other(err),
+        }
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::NotFound => write!(f, "service does not exist"),
+            ServiceError::AccessDenied => write!(f, "access denied (run as administrator)"),
+            ServiceError::AlreadyExists => write!(f, "service already exists"),
+            ServiceError::Timeout => write!(f, "timed out waiting for the service"),
+            ServiceError::MarkedForDelete => write!(f, "service is marked for deletion"),
+            ServiceError::Unreachable(code) => write!(
+                f,
+                "computer is unreachable: {}",
+                io::Error::from_raw_os_error(*code)
+            ),
+            ServiceError::Os(code) => write!(f, "{}", io::Error::from_raw_os_error(*code)),
+            ServiceError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServiceError::Other(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let table = [
+            (ERROR_SERVICE_DOES_NOT_EXIST, 10),
+            (ERROR_ACCESS_DENIED, 11),
+            (ERROR_SERVICE_EXISTS, 12),
+            (ERROR_SERVICE_REQUEST_TIMEOUT, 13),
+            (ERROR_SERVICE_MARKED_FOR_DELETE, 14),
+            (ERROR_SERVICE_DISABLED, 15),
+            (RPC_S_SERVER_UNAVAILABLE, 17),
+            (ERROR_BAD_NETPATH, 17),
+            (ERROR_INVALID_COMPUTERNAME, 17),
+        ];
+        for (code, exit_code) in table {
+            let err = ServiceError::from(io::Error::from_raw_os_error(code));
+            assert_eq!(err.exit_code(), exit_code, "{}", code);
+            assert_eq!(err.code(), Some(code));
+        }
+        let other = ServiceError::from(io::Error::new(io::ErrorKind::InvalidInput, "bad name"));
+        assert_eq!(other.exit_code(), 16);
+        assert_eq!(other.code(), None);
+    }
+}
@@ -0,0 +1,384 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    io,
+    sync::Mutex,
+    time::Duration,
+};
+
+use super::*;
+
+/// Operations of [`FakeServiceController`] that can be made to fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FakeOp {
+    Open,
+    QueryStatus,
+    QueryConfig,
+    Create,
+    Delete,
+    Start,
+    Stop,
+    ChangeConfig,
+    Notify,
+    QuerySecurity,
+    SetSecurity,
+}
+
+#[derive(Debug, Clone)]
+struct FakeService {
+    config: ServiceConfig,
+    status: ServiceStatus,
+    marked_for_delete: bool,
+    /// User-defined control codes received, oldest first.
+    notifications: Vec<u32>,
+    options: ServiceOptions,
+    /// DACL as SDDL.
+    security: String,
+}
+
+/// In-memory SCM.
+///
+/// `start`/`stop` move a service into `StartPending`/`StopPending`; every
+/// following `query_status` advances the checkpoint until `pending_polls` is
+/// reached and the service settles in `Running`/`Stopped`. A service deleted
+/// while running is only removed once it has stopped, and is reported as
+/// `ERROR_SERVICE_MARKED_FOR_DELETE` until then.
+///
+/// New services get the default Windows DACL. Only the well-known groups and
+/// accounts added with [`FakeServiceController::add_account`] resolve.
+#[derive(Debug, Default)]
+pub struct FakeServiceController {
+    services: Mutex<BTreeMap<String, FakeService>>,
+    accounts: Mutex<BTreeMap<String, String>>,
+    failures: Mutex<HashMap<(FakeOp, String), i32>>,
+    pending_polls: u32,
+}
+
+impl FakeServiceController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of `query_status` calls a pending service needs to settle.
+    pub fn with_pending_polls(mut self, polls: u32) -> Self {
+        self.pending_polls = polls;
+        self
+    }
+
+    /// Adds a service in the given state.
+    pub fn insert(&self, name: &str, start_type: StartType, state: ServiceState) {
+        let config = ServiceConfig {
+            display_name: OsString::from(name),
+            service_type: SERVICE_WIN32_OWN_PROCESS,
+            start_type,
+            error_control: SERVICE_ERROR_NORMAL,
+            executable_path: format!(r#"C:\Windows\system32\svchost.exe -k {}"#, name).into(),
+            dependencies: vec![],
+            account_name: Some(OsString::from("LocalSystem")),
+        };
+        self.services.lock().unwrap().insert(
+            name.to_ascii_lowercase(),
+            FakeService {
+                config,
+                status: ServiceStatus::new(state),
+                marked_for_delete: false,
+                notifications: vec![],
+                options: ServiceOptions::default(),
+                security: security::hardened_dacl(None),
+            },
+        );
+    }
+
+    /// Makes `account` resolve to the SDDL trustee `sid`.
+    pub fn add_account(&self, account: &str, sid: &str) {
+        self.accounts
+            .lock()
+            .unwrap()
+            .insert(account.to_ascii_lowercase(), sid.to_owned());
+    }
+
+    /// Makes the next `op` on `name` fail with the Win32 error `code`.
+    pub fn fail_next(&self, op: FakeOp, name: &str, code: i32) {
+        self.failures
+            .lock()
+            .unwrap()
+            .insert((op, name.to_ascii_lowercase()), code);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.services
+            .lock()
+            .unwrap()
+            .contains_key(&name.to_ascii_lowercase())
+    }
+
+    pub fn start_type(&self, name: &str) -> Option<StartType> {
+        self.services
+            .lock()
+            .unwrap()
+            .get(&name.to_ascii_lowercase())
+            .map(|s| s.config.start_type)
+    }
+
+    pub fn state(&self, name: &str) -> Option<ServiceState> {
+        self.services
+            .lock()
+            .unwrap()
+            .get(&name.to_ascii_lowercase())
+            .map(|s| s.status.current_state)
+    }
+
+    /// User-defined control codes `name` has received, oldest first.
+    pub fn notifications(&self, name: &str) -> Vec<u32> {
+        self.services
+            .lock()
+            .unwrap()
+            .get(&name.to_ascii_lowercase())
+            .map(|s| s.notifications.clone())
+            .unwrap_or_default()
+    }
+
+    /// The `ChangeServiceConfig2` settings applied to `name` so far.
+    pub fn options(&self, name: &str) -> Option<ServiceOptions> {
+        self.services
+            .lock()
+            .unwrap()
+            .get(&name.to_ascii_lowercase())
+            .map(|s| s.options.clone())
+    }
+
+    fn check(&self, op: FakeOp, name: &str) -> io::Result<()> {
+        match self
+            .failures
+            .lock()
+            .unwrap()
+            .remove(&(op, name.to_ascii_lowercase()))
+        {
+            Some(code) => Err(io::Error::from_raw_os_error(code)),
+            None => Ok(()),
+        }
+    }
+
+    fn with_service<T>(
+        &self,
+        op: FakeOp,
+        name: &str,
+        f: impl FnOnce(&mut FakeService) -> io::Result<T>,
+    ) -> io::Result<T> {
+        self.check(op, name)?;
+        let mut services = self.services.lock().unwrap();
+        match services.get_mut(&name.to_ascii_lowercase()) {
+            Some(service) if service.marked_for_delete && op != FakeOp::QueryStatus => Err(
+                io::Error::from_raw_os_error(ERROR_SERVICE_MARKED_FOR_DELETE),
+            ),
+            Some(service) => f(service),
+            None => Err(io::Error::from_raw_os_error(ERROR_SERVICE_DOES_NOT_EXIST)),
+        }
+    }
+
+    fn pending(state: ServiceState) -> ServiceStatus {
+        ServiceStatus {
+            wait_hint: Duration::from_millis(100),
+            ..ServiceStatus::new(state)
+        }
+    }
+}
+
+impl ServiceController for FakeServiceController {
+    fn open(&self, name: &str) -> io::Result<()> {
+        self.with_service(FakeOp::Open, name, |_| Ok(()))
+    }
+
+    fn query_status(&self, name: &str) -> io::Result<ServiceStatus> {
+        let status = self.with_service(FakeOp::QueryStatus, name, |service| {
+            let settled = match service.status.current_state {
+                ServiceState::StartPending | ServiceState::ContinuePending => {
+                    Some(ServiceState::Running)
+                }
+                ServiceState::StopPending => Some(ServiceState::Stopped),
+                ServiceState::PausePending => Some(ServiceState::Paused),
+                _ => None,
+            };
+            if let Some(settled) = settled {
+                if service.status.checkpoint >= self.pending_polls {
+                    service.status = ServiceStatus::new(settled);
+                } else {
+                    service.status.checkpoint += 1;
+                }
+            }
+            Ok(service.status.clone())
+        })?;
+
+        if status.current_state == ServiceState::Stopped {
+            let mut services = self.services.lock().unwrap();
+            let key = name.to_ascii_lowercase();
+            if services.get(&key).is_some_and(|s| s.marked_for_delete) {
+                services.remove(&key);
+            }
+        }
+        Ok(status)
+    }
+
+    fn query_config(&self, name: &str) -> io::Result<ServiceConfig> {
+        self.with_service(FakeOp::QueryConfig, name, |service| Ok(service.config.clone()))
+    }
+
+    fn create(&self, spec: &ServiceSpec) -> io::Result<()> {
+        let name = spec.name.to_string_lossy().to_string();
+        self.check(FakeOp::Create, &name)?;
+        let mut services = self.services.lock().unwrap();
+        let key = name.to_ascii_lowercase();
+        match services.get(&key) {
+            Some(service) if service.marked_for_delete => Err(io::Error::from_raw_os_error(
+                ERROR_SERVICE_MARKED_FOR_DELETE,
+            )),
+            Some(_) => Err(io::Error::from_raw_os_error(ERROR_SERVICE_EXISTS)),
+            None => {
+                let mut service = FakeService {
+                    config: config_from_spec(spec),
+                    status: ServiceStatus::new(ServiceState::Stopped),
+                    marked_for_delete: false,
+                    notifications: vec![],
+                    options: ServiceOptions::default(),
+                    security: security::hardened_dacl(None),
+                };
+                merge_options(&mut service.options, &spec.options);
+                services.insert(key, service);
+                Ok(())
+            }
+        }
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.with_service(FakeOp::Delete, name, |service| {
+            service.marked_for_delete = true;
+            Ok(())
+        })?;
+        // A stopped service goes away as soon as it is marked.
+        let mut services = self.services.lock().unwrap();
+        let key = name.to_ascii_lowercase();
+        if services
+            .get(&key)
+            .is_some_and(|s| s.status.current_state == ServiceState::Stopped)
+        {
+            services.remove(&key);
+        }
+        Ok(())
+    }
+
+    fn start(&self, name: &str) -> io::Result<()> {
+        let polls = self.pending_polls;
+        self.with_service(FakeOp::Start, name, |service| {
+            if service.config.start_type == StartType::Disabled {
+                return Err(io::Error::from_raw_os_error(ERROR_SERVICE_DISABLED));
+            }
+            if service.status.current_state != ServiceState::Stopped {
+                return Err(io::Error::from_raw_os_error(ERROR_SERVICE_ALREADY_RUNNING));
+            }
+            service.status = if polls == 0 {
+                ServiceStatus::new(ServiceState::Running)
+            } else {
+                Self::pending(ServiceState::StartPending)
+            };
+            Ok(())
+        })
+    }
+
+    fn stop(&self, name: &str) -> io::Result<ServiceStatus> {
+        let polls = self.pending_polls;
+        self.with_service(FakeOp::Stop, name, |service| {
+            if service.status.current_state == ServiceState::Stopped {
+                return Err(io::Error::from_raw_os_error(ERROR_SERVICE_NOT_ACTIVE));
+            }
+            service.status = if polls == 0 {
+                ServiceStatus::new(ServiceState::Stopped)
+            } else {
+                Self::pending(ServiceState::StopPending)
+            };
+            Ok(service.status.clone())
+        })
+    }
+
+    fn change_config(&self, name: &str, spec: &ServiceSpec) -> io::Result<()> {
+        self.with_service(FakeOp::ChangeConfig, name, |service| {
+            service.config = config_from_spec(spec);
+            merge_options(&mut service.options, &spec.options);
+            Ok(())
+        })
+    }
+
+    fn notify(&self, name: &str, code: u32) -> io::Result<ServiceStatus> {
+        self.with_service(FakeOp::Notify, name, |service| {
+            if !(128..=255).contains(&code) {
+                return Err(io::Error::from_raw_os_error(ERROR_INVALID_PARAMETER));
+            }
+            if service.status.current_state == ServiceState::Stopped {
+                return Err(io::Error::from_raw_os_error(ERROR_SERVICE_NOT_ACTIVE));
+            }
+            service.notifications.push(code);
+            Ok(service.status.clone())
+        })
+    }
+
+    fn query_security(&self, name: &str) -> io::Result<String> {
+        self.with_service(FakeOp::QuerySecurity, name, |service| Ok(service.security.clone()))
+    }
+
+    fn set_security(&self, name: &str, sddl: &str) -> io::Result<()> {
+        self.with_service(FakeOp::SetSecurity, name, |service| {
+            if !sddl.starts_with("D:") {
+                return Err(io::Error::from_raw_os_error(ERROR_INVALID_PARAMETER));
+            }
+            service.security = sddl.to_owned();
+            Ok(())
+        })
+    }
+
+    fn lookup_account(&self, account: &str) -> io::Result<String> {
+        let sid = match account.to_ascii_lowercase().as_str() {
+            "administrators" | "builtin\\administrators" => Some("BA".to_owned()),
+            "users" | "builtin\\users" => Some("BU".to_owned()),
+            "system" | "nt authority\\system" => Some("SY".to_owned()),
+            key => self.accounts.lock().unwrap().get(key).cloned(),
+        };
+        sid.ok_or_else(|| io::Error::from_raw_os_error(ERROR_NONE_MAPPED))
+    }
+}
+
+fn merge_options(current: &mut ServiceOptions, update: &ServiceOptions) {
+    if let Some(description) = &update.description {
+        current.description = Some(description.clone());
+    }
+    if let Some(delayed) = update.delayed_auto_start {
+        current.delayed_auto_start = Some(delayed);
+    }
+    if let Some(actions) = &update.failure_actions {
+        current.failure_actions = Some(actions.clone());
+    }
+    if let Some(enabled) = update.failure_actions_on_non_crash {
+        current.failure_actions_on_non_crash = Some(enabled);
+    }
+    if let Some(sid_type) = update.sid_type {
+        current.sid_type = Some(sid_type);
+    }
+}
+
+fn config_from_spec(spec: &ServiceSpec) -> ServiceConfig {
+    let mut command = OsString::from(spec.executable_path.as_os_str());
+    for arg in &spec.launch_arguments {
+        command.push(" ");
+        command.push(arg);
+    }
+    ServiceConfig {
+        display_name: spec.display_name.clone(),
+        service_type: spec.service_type,
+        start_type: spec.start_type,
+        error_control: spec.error_control,
+        executable_path: command.into(),
+        dependencies: spec.dependencies.clone(),
+        account_name: spec
+            .account_name
+            .clone()
+            .or_else(|| Some(OsString::from("LocalSystem"))),
+    }
+}
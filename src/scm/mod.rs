@@ -0,0 +1,200 @@
+//! Service Control Manager abstraction.
+//!
+//! Everything in `service.rs` and `kill_update.rs` talks to the SCM through
+//! [`ServiceController`], so the same flows run against the real Windows SCM
+//! ([`WindowsServiceController`]) or the in-memory [`FakeServiceController`].
+//! Errors are plain `std::io::Error`s carrying the Win32 error code, exactly as
+//! `windows_service::Error::Winapi` reports them.
+
+use std::{
+    ffi::OsString,
+    io,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+mod error;
+mod fake;
+pub mod security;
+#[cfg(windows)]
+mod windows;
+
+pub use error::ServiceError;
+pub use fake::{FakeOp, FakeServiceController};
+#[cfg(windows)]
+pub use self::windows::WindowsServiceController;
+
+pub const ERROR_ACCESS_DENIED: i32 = 5;
+pub const ERROR_BAD_NETPATH: i32 = 53;
+pub const ERROR_INVALID_PARAMETER: i32 = 87;
+pub const ERROR_SERVICE_REQUEST_TIMEOUT: i32 = 1053;
+pub const ERROR_SERVICE_ALREADY_RUNNING: i32 = 1056;
+pub const ERROR_SERVICE_DISABLED: i32 = 1058;
+pub const ERROR_SERVICE_DOES_NOT_EXIST: i32 = 1060;
+pub const ERROR_SERVICE_CANNOT_ACCEPT_CTRL: i32 = 1061;
+pub const ERROR_SERVICE_NOT_ACTIVE: i32 = 1062;
+pub const ERROR_SERVICE_MARKED_FOR_DELETE: i32 = 1072;
+pub const ERROR_SERVICE_EXISTS: i32 = 1073;
+pub const ERROR_INVALID_COMPUTERNAME: i32 = 1210;
+pub const ERROR_NONE_MAPPED: i32 = 1332;
+pub const RPC_S_SERVER_UNAVAILABLE: i32 = 1722;
+
+/// `SERVICE_WIN32_OWN_PROCESS`
+pub const SERVICE_WIN32_OWN_PROCESS: u32 = 0x10;
+/// `SERVICE_ERROR_NORMAL`
+pub const SERVICE_ERROR_NORMAL: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StartType {
+    AutoStart,
+    OnDemand,
+    Disabled,
+    /// Drivers only.
+    SystemStart,
+    /// Drivers only.
+    BootStart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceState {
+    Stopped,
+    StartPending,
+    StopPending,
+    Running,
+    ContinuePending,
+    PausePending,
+    Paused,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceStatus {
+    pub current_state: ServiceState,
+    pub checkpoint: u32,
+    pub wait_hint: Duration,
+    pub process_id: Option<u32>,
+}
+
+impl ServiceStatus {
+    pub fn new(current_state: ServiceState) -> Self {
+        Self {
+            current_state,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        }
+    }
+}
+
+/// Configuration of an installed service, as returned by `QueryServiceConfig`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceConfig {
+    pub display_name: OsString,
+    /// Raw `SERVICE_*` type bits.
+    pub service_type: u32,
+    pub start_type: StartType,
+    /// Raw `SERVICE_ERROR_*` value.
+    pub error_control: u32,
+    /// Full command line (binary path and arguments) of the service.
+    pub executable_path: PathBuf,
+    pub dependencies: Vec<OsString>,
+    pub account_name: Option<OsString>,
+}
+
+/// Description of a service to create or reconfigure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSpec {
+    pub name: OsString,
+    pub display_name: OsString,
+    /// Raw `SERVICE_*` type bits.
+    pub service_type: u32,
+    pub start_type: StartType,
+    /// Raw `SERVICE_ERROR_*` value.
+    pub error_control: u32,
+    pub executable_path: PathBuf,
+    pub launch_arguments: Vec<OsString>,
+    pub dependencies: Vec<OsString>,
+    /// `None` runs the service as LocalSystem.
+    pub account_name: Option<OsString>,
+    pub account_password: Option<OsString>,
+    pub options: ServiceOptions,
+}
+
+/// Settings applied with `ChangeServiceConfig2` after the service is created
+/// or reconfigured; `None` leaves the current setting unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceOptions {
+    pub description: Option<OsString>,
+    /// Start shortly after the other auto-start services, only honored for `AutoStart`.
+    pub delayed_auto_start: Option<bool>,
+    pub failure_actions: Option<FailureActions>,
+    /// Also run the failure actions when the service stops with a non-zero exit code.
+    pub failure_actions_on_non_crash: Option<bool>,
+    pub sid_type: Option<SidType>,
+}
+
+/// Restart the service when it fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailureActions {
+    /// Delay before the restart after the first, second, ... failure; the last
+    /// one repeats for every later failure.
+    pub restart_delays: Vec<Duration>,
+    /// The failure count goes back to zero after this long without a failure,
+    /// `None` never resets it.
+    pub reset_period: Option<Duration>,
+}
+
+/// How the service SID is added to the service process token (`SERVICE_SID_INFO`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SidType {
+    None,
+    Unrestricted,
+    Restricted,
+}
+
+impl FromStr for SidType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(SidType::None),
+            "unrestricted" => Ok(SidType::Unrestricted),
+            "restricted" => Ok(SidType::Restricted),
+            _ => Err(format!(
+                "invalid SID type {:?}, expected none, unrestricted or restricted",
+                s
+            )),
+        }
+    }
+}
+
+pub trait ServiceController {
+    /// Opens the service, failing with `ERROR_SERVICE_DOES_NOT_EXIST` when it is not installed.
+    fn open(&self, name: &str) -> io::Result<()>;
+    fn query_status(&self, name: &str) -> io::Result<ServiceStatus>;
+    fn query_config(&self, name: &str) -> io::Result<ServiceConfig>;
+    /// Creates the service and applies `spec.options`.
+    fn create(&self, spec: &ServiceSpec) -> io::Result<()>;
+    /// Marks the service for deletion; it disappears once it is stopped and all handles are closed.
+    fn delete(&self, name: &str) -> io::Result<()>;
+    fn start(&self, name: &str) -> io::Result<()>;
+    fn stop(&self, name: &str) -> io::Result<ServiceStatus>;
+    /// Reconfigures the service and applies `spec.options`.
+    fn change_config(&self, name: &str, spec: &ServiceSpec) -> io::Result<()>;
+    /// Sends the user-defined control `code` (128..=255) to a running service.
+    fn notify(&self, name: &str, code: u32) -> io::Result<ServiceStatus>;
+    /// The DACL of the service as SDDL, e.g. `D:(A;;CCLCSWLOCRRC;;;IU)...`.
+    fn query_security(&self, name: &str) -> io::Result<String>;
+    /// Replaces the DACL of the service with the one in `sddl`.
+    fn set_security(&self, name: &str, sddl: &str) -> io::Result<()>;
+    /// Resolves a user or group name on the SCM's computer to the SDDL trustee
+    /// (SID string or alias such as `BU`) the DACL reports it as; fails with
+    /// `ERROR_NONE_MAPPED` for unknown accounts.
+    fn lookup_account(&self, account: &str) -> io::Result<String>;
+}
+
+pub fn is_not_found(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST)
+}
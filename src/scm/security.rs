@@ -0,0 +1,138 @@
+//! Service DACLs in SDDL form.
+//!
+//! Service access rights in SDDL: `CC` query config, `DC` change config,
+//! `LC` query status, `SW` enumerate dependents, `RP` start, `WP` stop,
+//! `DT` pause/continue, `LO` interrogate, `CR` user-defined controls,
+//! `SD` delete, `RC` read permissions, `WD` change permissions, `WO` take
+//! ownership. Rights are written in the order Windows prints them, so a DACL
+//! read back from the SCM compares equal to the one that was written.
+
+/// SYSTEM: everything but changing the config, deleting and the DACL itself.
+const SYSTEM_ACE: &str = "(A;;CCLCSWRPWPDTLOCRRC;;;SY)";
+/// Administrators: full control.
+const ADMINISTRATORS_ACE: &str = "(A;;CCDCLCSWRPWPDTLOCRSDRCWDWO;;;BA)";
+/// Interactive and service logons: query only.
+const READERS_ACES: &str = "(A;;CCLCSWLOCRRC;;;IU)(A;;CCLCSWLOCRRC;;;SU)";
+
+/// The ACE letting `trustee` (an SDDL SID string or alias) start, pause and
+/// query the service, and also stop it unless `can_stop` is false.
+pub fn operators_ace(trustee: &str, can_stop: bool) -> String {
+    let stop = if can_stop { "WP" } else { "" };
+    format!("(A;;CCLCSWRP{}DTLOCRRC;;;{})", stop, trustee)
+}
+
+/// The hardened DACL: only SYSTEM and Administrators may stop, reconfigure or
+/// delete the service, everyone else at most gets the operators ACE.
+///
+/// Without operators this is the DACL Windows gives a newly created service.
+/// SDDL has no "everyone except" form and a deny ACE for Everyone would also
+/// match Administrators, so the DACL is an allow list instead.
+pub fn hardened_dacl(operators: Option<&str>) -> String {
+    let mut dacl = format!("D:{}{}{}", SYSTEM_ACE, ADMINISTRATORS_ACE, READERS_ACES);
+    if let Some(trustee) = operators {
+        dacl.push_str(&operators_ace(trustee, false));
+    }
+    dacl
+}
+
+/// The ACEs of the DACL in `sddl`, e.g. `["(A;;CCLCSWLOCRRC;;;IU)", ...]`.
+///
+/// Parsing stops at the end of the DACL, so the ACEs of a SACL (`S:`) that
+/// follows it are not included.
+pub fn dacl_aces(sddl: &str) -> Vec<&str> {
+    let Some((flags, dacl)) = split_dacl(sddl) else {
+        return vec![];
+    };
+    let mut aces = vec![];
+    let mut rest = &dacl[flags.len()..];
+    while rest.starts_with('(') {
+        match rest.find(')') {
+            Some(close) => {
+                aces.push(&rest[..=close]);
+                rest = &rest[close + 1..];
+            }
+            None => break,
+        }
+    }
+    aces
+}
+
+/// `sddl` with `ace` appended to its DACL, unchanged when the ACE is already there.
+pub fn with_ace(sddl: &str, ace: &str) -> String {
+    let aces = dacl_aces(sddl);
+    if aces.contains(&ace) {
+        return sddl.to_owned();
+    }
+    // Keep the DACL flags (e.g. `P` for protected) that precede the first ACE.
+    let flags = split_dacl(sddl).map_or("", |(flags, _)| flags);
+    format!("D:{}{}{}", flags, aces.concat(), ace)
+}
+
+/// The flags of the DACL in `sddl` (e.g. `PAI`) and the DACL from there on.
+fn split_dacl(sddl: &str) -> Option<(&str, &str)> {
+    let dacl = &sddl[sddl.find("D:")? + 2..];
+    let mut end = dacl
+        .find(|c: char| !c.is_ascii_uppercase())
+        .unwrap_or(dacl.len());
+    // An empty DACL runs into the next part, e.g. the `S` of `D:PS:(...)`.
+    if dacl[end..].starts_with(':') {
+        end = end.saturating_sub(1);
+    }
+    Some((&dacl[..end], dacl))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS_SID: &str = "S-1-5-21-1-2-3-1001";
+
+    #[test]
+    fn hardened_dacl_is_the_default_service_dacl_plus_operators() {
+        assert_eq!(
+            hardened_dacl(None),
+            "D:(A;;CCLCSWRPWPDTLOCRRC;;;SY)(A;;CCDCLCSWRPWPDTLOCRSDRCWDWO;;;BA)\
+             (A;;CCLCSWLOCRRC;;;IU)(A;;CCLCSWLOCRRC;;;SU)"
+        );
+        // Hardened operators may start and pause, but not stop.
+        let hardened = hardened_dacl(Some(OPERATORS_SID));
+        assert_eq!(
+            hardened,
+            format!(
+                "{}(A;;CCLCSWRPDTLOCRRC;;;{})",
+                hardened_dacl(None),
+                OPERATORS_SID
+            )
+        );
+        assert_eq!(
+            operators_ace(OPERATORS_SID, true),
+            format!("(A;;CCLCSWRPWPDTLOCRRC;;;{})", OPERATORS_SID)
+        );
+    }
+
+    #[test]
+    fn dacl_aces_reads_only_the_dacl() {
+        assert_eq!(
+            dacl_aces("O:SYG:SYD:PAI(A;;CCLCSWLOCRRC;;;IU)(A;;CCLCSWRPWPDTLOCRRC;;;SY)S:(AU;FA;CCDCLCSWRPWPDTLOCRSDRCWDWO;;;WD)"),
+            ["(A;;CCLCSWLOCRRC;;;IU)", "(A;;CCLCSWRPWPDTLOCRRC;;;SY)"]
+        );
+        assert_eq!(dacl_aces("D:PS:(AU;FA;CCLC;;;WD)"), Vec::<&str>::new());
+        assert_eq!(dacl_aces("O:SYG:SY"), Vec::<&str>::new());
+        // An unterminated ACE ends the DACL.
+        assert_eq!(dacl_aces("D:(A;;CC;;;IU)(A;;CC"), ["(A;;CC;;;IU)"]);
+    }
+
+    #[test]
+    fn with_ace_appends_once_and_keeps_the_flags() {
+        let ace = operators_ace(OPERATORS_SID, true);
+        let sddl = with_ace("D:P(A;;CCLCSWLOCRRC;;;IU)", &ace);
+        assert_eq!(sddl, format!("D:P(A;;CCLCSWLOCRRC;;;IU){}", ace));
+        assert_eq!(with_ace(&sddl, &ace), sddl);
+
+        assert_eq!(
+            with_ace("D:PS:(AU;FA;CCLC;;;WD)", &ace),
+            format!("D:P{}", ace)
+        );
+        assert_eq!(with_ace("O:SY", &ace), format!("D:{}", ace));
+    }
+}
@@ -0,0 +1,418 @@
+use std::{ffi::OsStr, io, ptr};
+
+use windows_service::{
+    service::{
+        Service, ServiceAccess, ServiceAction, ServiceActionType, ServiceDependency,
+        ServiceErrorControl, ServiceFailureActions, ServiceFailureResetPeriod, ServiceInfo,
+        ServiceSidType, ServiceStartType, ServiceState as WinServiceState,
+        ServiceStatus as WinServiceStatus, ServiceType, UserEventCode,
+    },
+    service_manager::{ServiceManager, ServiceManagerAccess},
+};
+use windows_sys::{
+    core::PWSTR,
+    Win32::{
+        Foundation::{LocalFree, ERROR_INSUFFICIENT_BUFFER},
+        Security::{
+            Authorization::{
+                ConvertSecurityDescriptorToStringSecurityDescriptorW, ConvertSidToStringSidW,
+                ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+            },
+            LookupAccountNameW, DACL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR, SC_HANDLE,
+        },
+        Storage::FileSystem::{READ_CONTROL, WRITE_DAC},
+        System::Services::{QueryServiceObjectSecurity, SetServiceObjectSecurity},
+    },
+};
+
+use super::*;
+
+/// [`ServiceController`] backed by the Service Control Manager of the local
+/// or a remote computer.
+#[derive(Debug, Default, Clone)]
+pub struct WindowsServiceController {
+    /// `None` for the local computer.
+    computer: Option<OsString>,
+}
+
+impl WindowsServiceController {
+    pub fn local() -> Self {
+        Self { computer: None }
+    }
+
+    /// The SCM of `computer`, by host name or address.
+    pub fn remote(computer: impl AsRef<OsStr>) -> Self {
+        Self {
+            computer: Some(computer.as_ref().to_os_string()),
+        }
+    }
+
+    /// The remote computer, `None` for the local one.
+    pub fn computer(&self) -> Option<&OsStr> {
+        self.computer.as_deref()
+    }
+
+    fn manager(&self, access: ServiceManagerAccess) -> io::Result<ServiceManager> {
+        match &self.computer {
+            Some(computer) => ServiceManager::remote_computer(computer, None::<&str>, access),
+            None => ServiceManager::local_computer(None::<&str>, access),
+        }
+        .map_err(into_io)
+    }
+
+    fn with_service<T>(
+        &self,
+        name: &str,
+        access: ServiceAccess,
+        f: impl FnOnce(&Service) -> windows_service::Result<T>,
+    ) -> io::Result<T> {
+        let service_manager = self.manager(ServiceManagerAccess::CONNECT)?;
+        let service = service_manager
+            .open_service(name, access)
+            .map_err(into_io)?;
+        f(&service).map_err(into_io)
+    }
+}
+
+impl ServiceController for WindowsServiceController {
+    fn open(&self, name: &str) -> io::Result<()> {
+        self.with_service(name, ServiceAccess::QUERY_CONFIG, |_| Ok(()))
+    }
+
+    fn query_status(&self, name: &str) -> io::Result<ServiceStatus> {
+        self.with_service(name, ServiceAccess::QUERY_STATUS, |service| {
+            service.query_status().map(status_from_win)
+        })
+    }
+
+    fn query_config(&self, name: &str) -> io::Result<ServiceConfig> {
+        self.with_service(name, ServiceAccess::QUERY_CONFIG, |service| {
+            let config = service.query_config()?;
+            Ok(ServiceConfig {
+                display_name: config.display_name,
+                service_type: config.service_type.bits(),
+                start_type: start_type_from_win(config.start_type),
+                error_control: config.error_control.to_raw(),
+                executable_path: config.executable_path,
+                dependencies: config
+                    .dependencies
+                    .iter()
+                    .map(|x| x.to_system_identifier())
+                    .collect(),
+                account_name: config.account_name,
+            })
+        })
+    }
+
+    fn create(&self, spec: &ServiceSpec) -> io::Result<()> {
+        let manager_access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE;
+        let service_manager = self.manager(manager_access)?;
+        service_manager
+            .create_service(&info_from_spec(spec), ServiceAccess::CHANGE_CONFIG)
+            .and_then(|service| apply_options(&service, &spec.options))
+            .map_err(into_io)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.with_service(name, ServiceAccess::DELETE, |service| service.delete())
+    }
+
+    fn start(&self, name: &str) -> io::Result<()> {
+        self.with_service(name, ServiceAccess::START, |service| {
+            service.start(Vec::<&str>::new().as_slice())
+        })
+    }
+
+    fn stop(&self, name: &str) -> io::Result<ServiceStatus> {
+        self.with_service(name, ServiceAccess::STOP, |service| {
+            service.stop().map(status_from_win)
+        })
+    }
+
+    fn change_config(&self, name: &str, spec: &ServiceSpec) -> io::Result<()> {
+        self.with_service(name, ServiceAccess::CHANGE_CONFIG, |service| {
+            service.change_config(&info_from_spec(spec))?;
+            apply_options(service, &spec.options)
+        })
+    }
+
+    fn notify(&self, name: &str, code: u32) -> io::Result<ServiceStatus> {
+        let code = UserEventCode::from_raw(code)
+            .map_err(|_| io::Error::from_raw_os_error(ERROR_INVALID_PARAMETER))?;
+        self.with_service(name, ServiceAccess::USER_DEFINED_CONTROL, |service| {
+            service.notify(code).map(status_from_win)
+        })
+    }
+
+    fn query_security(&self, name: &str) -> io::Result<String> {
+        let access = ServiceAccess::from_bits_retain(READ_CONTROL);
+        self.with_service(name, access, |service| {
+            unsafe { query_dacl(service.raw_handle()) }.map_err(windows_service::Error::Winapi)
+        })
+    }
+
+    fn set_security(&self, name: &str, sddl: &str) -> io::Result<()> {
+        let access = ServiceAccess::from_bits_retain(WRITE_DAC);
+        self.with_service(name, access, |service| {
+            unsafe { set_dacl(service.raw_handle(), sddl) }.map_err(windows_service::Error::Winapi)
+        })
+    }
+
+    fn lookup_account(&self, account: &str) -> io::Result<String> {
+        let computer = self.computer.as_ref().map(|c| wide(&c.to_string_lossy()));
+        let sid = unsafe { lookup_sid(computer.as_deref(), &wide(account))? };
+        // Let Windows pick the alias of a well-known SID, as the DACL reports it.
+        let sddl = canonical_sddl(&format!("D:(A;;RC;;;{})", sid))?;
+        security::dacl_aces(&sddl)
+            .first()
+            .and_then(|ace| ace.trim_end_matches(')').rsplit(';').next())
+            .map(str::to_owned)
+            .ok_or_else(|| io::Error::from_raw_os_error(ERROR_NONE_MAPPED))
+    }
+}
+
+/// Memory the security APIs allocate with `LocalAlloc`.
+struct LocalMemory<T>(*mut T);
+
+impl<T> Drop for LocalMemory<T> {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { LocalFree(self.0.cast()) };
+        }
+    }
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+unsafe fn from_wide(s: PWSTR) -> String {
+    let mut len = 0;
+    while *s.add(len) != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(std::slice::from_raw_parts(s, len))
+}
+
+fn last_error<T>() -> io::Result<T> {
+    Err(io::Error::last_os_error())
+}
+
+unsafe fn sddl_to_descriptor(sddl: &str) -> io::Result<LocalMemory<core::ffi::c_void>> {
+    let mut descriptor: PSECURITY_DESCRIPTOR = ptr::null_mut();
+    let sddl = wide(sddl);
+    if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+        sddl.as_ptr(),
+        SDDL_REVISION_1,
+        &mut descriptor,
+        ptr::null_mut(),
+    ) == 0
+    {
+        return last_error();
+    }
+    Ok(LocalMemory(descriptor))
+}
+
+unsafe fn descriptor_to_sddl(descriptor: PSECURITY_DESCRIPTOR) -> io::Result<String> {
+    let mut sddl: PWSTR = ptr::null_mut();
+    if ConvertSecurityDescriptorToStringSecurityDescriptorW(
+        descriptor,
+        SDDL_REVISION_1,
+        DACL_SECURITY_INFORMATION,
+        &mut sddl,
+        ptr::null_mut(),
+    ) == 0
+    {
+        return last_error();
+    }
+    let sddl = LocalMemory(sddl);
+    Ok(from_wide(sddl.0))
+}
+
+/// `sddl` as Windows writes it back, with well-known SIDs replaced by their alias.
+fn canonical_sddl(sddl: &str) -> io::Result<String> {
+    unsafe {
+        let descriptor = sddl_to_descriptor(sddl)?;
+        descriptor_to_sddl(descriptor.0)
+    }
+}
+
+unsafe fn query_dacl(service: SC_HANDLE) -> io::Result<String> {
+    let mut needed = 0;
+    if QueryServiceObjectSecurity(
+        service,
+        DACL_SECURITY_INFORMATION,
+        ptr::null_mut(),
+        0,
+        &mut needed,
+    ) == 0
+    {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER as i32) {
+            return Err(err);
+        }
+    }
+    let mut buffer = vec![0u8; needed as usize];
+    if QueryServiceObjectSecurity(
+        service,
+        DACL_SECURITY_INFORMATION,
+        buffer.as_mut_ptr().cast(),
+        needed,
+        &mut needed,
+    ) == 0
+    {
+        return last_error();
+    }
+    descriptor_to_sddl(buffer.as_mut_ptr().cast())
+}
+
+unsafe fn set_dacl(service: SC_HANDLE, sddl: &str) -> io::Result<()> {
+    let descriptor = sddl_to_descriptor(sddl)?;
+    if SetServiceObjectSecurity(service, DACL_SECURITY_INFORMATION, descriptor.0) == 0 {
+        return last_error();
+    }
+    Ok(())
+}
+
+/// The SID of `account` as a string, looked up on `computer` (NUL-terminated
+/// UTF-16, `None` for the local one).
+unsafe fn lookup_sid(computer: Option<&[u16]>, account: &[u16]) -> io::Result<String> {
+    let computer = computer.map_or(ptr::null(), |c| c.as_ptr());
+    let (mut sid_len, mut domain_len, mut sid_use) = (0, 0, 0);
+    LookupAccountNameW(
+        computer,
+        account.as_ptr(),
+        ptr::null_mut(),
+        &mut sid_len,
+        ptr::null_mut(),
+        &mut domain_len,
+        &mut sid_use,
+    );
+    if sid_len == 0 {
+        return last_error();
+    }
+    let mut sid = vec![0u8; sid_len as usize];
+    let mut domain = vec![0u16; domain_len as usize];
+    if LookupAccountNameW(
+        computer,
+        account.as_ptr(),
+        sid.as_mut_ptr().cast(),
+        &mut sid_len,
+        domain.as_mut_ptr(),
+        &mut domain_len,
+        &mut sid_use,
+    ) == 0
+    {
+        return last_error();
+    }
+    let mut text: PWSTR = ptr::null_mut();
+    if ConvertSidToStringSidW(sid.as_mut_ptr().cast(), &mut text) == 0 {
+        return last_error();
+    }
+    let text = LocalMemory(text);
+    Ok(from_wide(text.0))
+}
+
+fn into_io(err: windows_service::Error) -> io::Error {
+    match err {
+        windows_service::Error::Winapi(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidInput, e.to_string()),
+    }
+}
+
+fn status_from_win(status: WinServiceStatus) -> ServiceStatus {
+    ServiceStatus {
+        current_state: match status.current_state {
+            WinServiceState::Stopped => ServiceState::Stopped,
+            WinServiceState::StartPending => ServiceState::StartPending,
+            WinServiceState::StopPending => ServiceState::StopPending,
+            WinServiceState::Running => ServiceState::Running,
+            WinServiceState::ContinuePending => ServiceState::ContinuePending,
+            WinServiceState::PausePending => ServiceState::PausePending,
+            WinServiceState::Paused => ServiceState::Paused,
+        },
+        checkpoint: status.checkpoint,
+        wait_hint: status.wait_hint,
+        process_id: status.process_id,
+    }
+}
+
+fn start_type_from_win(start_type: ServiceStartType) -> StartType {
+    match start_type {
+        ServiceStartType::AutoStart => StartType::AutoStart,
+        ServiceStartType::OnDemand => StartType::OnDemand,
+        ServiceStartType::Disabled => StartType::Disabled,
+        ServiceStartType::SystemStart => StartType::SystemStart,
+        ServiceStartType::BootStart => StartType::BootStart,
+    }
+}
+
+fn start_type_to_win(start_type: StartType) -> ServiceStartType {
+    match start_type {
+        StartType::AutoStart => ServiceStartType::AutoStart,
+        StartType::OnDemand => ServiceStartType::OnDemand,
+        StartType::Disabled => ServiceStartType::Disabled,
+        StartType::SystemStart => ServiceStartType::SystemStart,
+        StartType::BootStart => ServiceStartType::BootStart,
+    }
+}
+
+fn apply_options(service: &Service, options: &ServiceOptions) -> windows_service::Result<()> {
+    if let Some(description) = &options.description {
+        service.set_description(description)?;
+    }
+    if let Some(delayed) = options.delayed_auto_start {
+        service.set_delayed_auto_start(delayed)?;
+    }
+    if let Some(actions) = &options.failure_actions {
+        service.update_failure_actions(ServiceFailureActions {
+            reset_period: match actions.reset_period {
+                Some(period) => ServiceFailureResetPeriod::After(period),
+                None => ServiceFailureResetPeriod::Never,
+            },
+            reboot_msg: None,
+            command: None,
+            actions: Some(
+                actions
+                    .restart_delays
+                    .iter()
+                    .map(|delay| ServiceAction {
+                        action_type: ServiceActionType::Restart,
+                        delay: *delay,
+                    })
+                    .collect(),
+            ),
+        })?;
+    }
+    if let Some(enabled) = options.failure_actions_on_non_crash {
+        service.set_failure_actions_on_non_crash_failures(enabled)?;
+    }
+    if let Some(sid_type) = options.sid_type {
+        service.set_config_service_sid_info(match sid_type {
+            SidType::None => ServiceSidType::None,
+            SidType::Unrestricted => ServiceSidType::Unrestricted,
+            SidType::Restricted => ServiceSidType::Restricted,
+        })?;
+    }
+    Ok(())
+}
+
+fn info_from_spec(spec: &ServiceSpec) -> ServiceInfo {
+    ServiceInfo {
+        name: spec.name.clone(),
+        display_name: spec.display_name.clone(),
+        service_type: ServiceType::from_bits_truncate(spec.service_type),
+        start_type: start_type_to_win(spec.start_type),
+        error_control: ServiceErrorControl::from_raw(spec.error_control)
+            .unwrap_or(ServiceErrorControl::Normal),
+        executable_path: spec.executable_path.clone(),
+        launch_arguments: spec.launch_arguments.clone(),
+        dependencies: spec
+            .dependencies
+            .iter()
+            .map(ServiceDependency::from_system_identifier)
+            .collect(),
+        account_name: spec.account_name.clone(),
+        account_password: spec.account_password.clone(),
+    }
+}
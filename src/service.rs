@@ -1,34 +1,57 @@
 use own_logger::*;
-use std::{
-    path::PathBuf,
-    time::{Duration, Instant}, ffi::{OsString, OsStr},
-};
+use std::io;
+#[cfg(windows)]
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+#[cfg(windows)]
 use windows_service::{
     service::{
-        ServiceAccess, ServiceConfig, ServiceControlAccept,
-        ServiceExitCode, ServiceInfo, ServiceState, ServiceStatus, ServiceType,
+        ServiceControlAccept, ServiceExitCode, ServiceState as WinServiceState, ServiceStatus,
+        ServiceType,
     },
-    // service_control_handler::{self, ServiceControlHandlerResult},
-    // service_dispatcher,
-    service_manager::{ServiceManager, ServiceManagerAccess},
+    service_control_handler::ServiceStatusHandle,
+};
+
+use crate::scm::{
+    is_not_found, ServiceConfig, ServiceController, ServiceError, ServiceSpec, ServiceState,
 };
-use windows_sys::Win32::Foundation::ERROR_SERVICE_DOES_NOT_EXIST;
 
-use anyhow::{anyhow, Result};
+type Result<T> = std::result::Result<T, ServiceError>;
+
+/// Name the blocker's own service is registered under.
+pub const SERVICE_NAME: &str = "WindowsUpdateBlocker.rs";
+
+/// How long `start`, `stop`, `uninstall` and `change_config` wait for the service to settle.
+pub const STATE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Bounds for the polling interval of [`wait_for_state`] and `wait_for_delete`.
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[cfg(windows)]
 pub const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
 
+#[cfg(windows)]
 pub trait ServiceStatusEx {
     fn running() -> ServiceStatus;
+    fn paused() -> ServiceStatus;
+    /// A `*Pending` state, `checkpoint` must grow on every report of the same transition.
+    fn pending(state: WinServiceState, checkpoint: u32, wait_hint: Duration) -> ServiceStatus;
     fn stopped() -> ServiceStatus;
     fn stopped_with_error(code: u32) -> ServiceStatus;
 }
 
+#[cfg(windows)]
 impl ServiceStatusEx for ServiceStatus {
     fn running() -> ServiceStatus {
         ServiceStatus {
             service_type: SERVICE_TYPE,
-            current_state: ServiceState::Running,
-            controls_accepted: ServiceControlAccept::STOP,
+            current_state: WinServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP
+                | ServiceControlAccept::PAUSE_CONTINUE
+                | ServiceControlAccept::POWER_EVENT
+                | ServiceControlAccept::SESSION_CHANGE
+                | ServiceControlAccept::PRESHUTDOWN,
             exit_code: ServiceExitCode::Win32(0),
             checkpoint: 0,
             wait_hint: Duration::default(),
@@ -36,9 +59,27 @@ impl ServiceStatusEx for ServiceStatus {
         }
     }
 
+    fn paused() -> ServiceStatus {
+        ServiceStatus {
+            current_state: WinServiceState::Paused,
+            ..Self::running()
+        }
+    }
+
+    fn pending(state: WinServiceState, checkpoint: u32, wait_hint: Duration) -> ServiceStatus {
+        ServiceStatus {
+            current_state: state,
+            // The SCM rejects controls while a transition is pending.
+            controls_accepted: ServiceControlAccept::empty(),
+            checkpoint,
+            wait_hint,
+            ..Self::running()
+        }
+    }
+
     fn stopped() -> ServiceStatus {
         ServiceStatus {
-            current_state: ServiceState::Stopped,
+            current_state: WinServiceState::Stopped,
             controls_accepted: ServiceControlAccept::empty(),
             ..Self::running()
         }
@@ -52,222 +93,554 @@ impl ServiceStatusEx for ServiceStatus {
     }
 }
 
-pub struct WindowsService {
-    // log: log::Logger,
-    pub name: OsString,
-    pub display_name: OsString,
-    pub executable_path: PathBuf,
-    pub arguments: Vec<OsString>,
+/// Reports the service status to the SCM, numbering the checkpoints of pending states.
+#[cfg(windows)]
+pub struct StatusReporter {
+    handle: ServiceStatusHandle,
+    checkpoint: AtomicU32,
 }
 
-/// Checks if a service is installed on Windows via name.
-fn service_exist(service_name: &str) -> bool {
-    let manager_access = ServiceManagerAccess::CONNECT;
-    if let Ok(service_manager) = ServiceManager::local_computer(None::<&str>, manager_access) {
-        if let Ok(_service) =
-            service_manager.open_service(service_name, ServiceAccess::QUERY_CONFIG)
-        {
-            return true;
+#[cfg(windows)]
+impl StatusReporter {
+    pub fn new(handle: ServiceStatusHandle) -> Self {
+        Self {
+            handle,
+            checkpoint: AtomicU32::new(0),
         }
     }
-    false
-}
 
-pub fn install(service: WindowsService, service_info: ServiceInfo) -> anyhow::Result<()> {
-    if service_exist(service.name.to_str().unwrap()) {
-        return Err(anyhow!("Service already exists"));
+    /// Reports progress of a pending transition; the SCM waits another
+    /// `wait_hint` from now before it considers the service hung.
+    pub fn pending(&self, state: WinServiceState, wait_hint: Duration) -> windows_service::Result<()> {
+        let checkpoint = self.checkpoint.fetch_add(1, Ordering::SeqCst) + 1;
+        self.handle
+            .set_service_status(ServiceStatus::pending(state, checkpoint, wait_hint))
     }
-    let manager_access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE;
-    let service_manager = match ServiceManager::local_computer(None::<&str>, manager_access) {
-        Ok(sm) => sm,
-        Err(_e) => return Err(anyhow!("Failed to connect to service manager")),
-    };
 
-    if let Err(_) = service_manager.create_service(&service_info, ServiceAccess::CHANGE_CONFIG) {
-        return Err(anyhow!("Failed to create service"));
+    /// Reports a settled state and resets the checkpoint for the next transition.
+    pub fn set(&self, status: ServiceStatus) -> windows_service::Result<()> {
+        self.checkpoint.store(0, Ordering::SeqCst);
+        self.handle.set_service_status(status)
     }
-
-    // let _ = service.set_description(OsString::from(service_description));
-    // grant_start_access_rights(&service.name)
-    info!("service installed");
-    Ok(())
 }
 
-/// Update the permissions so that any user can start the specified service
-// pub fn grant_start_access_rights(service_name: &str) -> Result<bool, BootstrapError> {
-//     let custom_manager = match dacl::CustomServiceManager::new() {
-//         Ok(sm) => sm,
-//         Err(_e) => return Err(BootstrapError::ServiceConnectionFailure),
-//     };
-//     match custom_manager.change_service_dacl(service_name) {
-//         Ok(_) => {
-//             log::info!("Successfully updated service");
-//             Ok(true)
-//         }
-//         Err(_) => Ok(false),
-//     }
-// }
-
-pub fn uninstall(service_name: &str) -> Result<()> {
-    if !service_exist(service_name) {
-        return Err(anyhow!("Service is no exist"));
-    }
-    let manager_access = ServiceManagerAccess::CONNECT;
-    let service_manager = match ServiceManager::local_computer(None::<&str>, manager_access) {
-        Ok(sm) => sm,
-        Err(_e) => return Err(anyhow!("Failed to connect to service manager")),
-    };
+/// User-defined control codes the blocker service reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserEvent {
+    /// Run an enforcement pass now.
+    EnforceNow = 128,
+    /// Reload the config file.
+    ReloadConfig = 129,
+    /// Close the current log file and start a new one.
+    RotateLogs = 130,
+}
 
-    let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE;
-    let service = match service_manager.open_service(service_name, service_access) {
-        Ok(s) => s,
-        Err(_e) => return Err(anyhow!("Failed to open service")),
-    };
-    let service_status = match service.query_status() {
-        Ok(s) => s,
-        Err(_e) => return Err(anyhow!("Failed to query service status")),
-    };
-    if service_status.current_state != ServiceState::Stopped {
-        if let Ok(_s) = service.stop() {
-            info!("Stopped {}", service_name);
+impl UserEvent {
+    pub fn from_raw(code: u32) -> Option<Self> {
+        match code {
+            128 => Some(UserEvent::EnforceNow),
+            129 => Some(UserEvent::ReloadConfig),
+            130 => Some(UserEvent::RotateLogs),
+            _ => None,
         }
     }
-    if let Ok(_s) = service.delete() {
-        info!("Deleteing {}", service_name);
+
+    pub fn to_raw(self) -> u32 {
+        self as u32
     }
+}
+
+/// Checks if a service is installed on Windows via name.
+///
+/// Fails with [`ServiceError::NotFound`] when it is not, and with the underlying
+/// error when the SCM could not be asked, e.g. [`ServiceError::AccessDenied`].
+fn service_exist(ctl: &dyn ServiceController, service_name: &str) -> Result<()> {
+    Ok(ctl.open(service_name)?)
+}
 
+/// Polls `service_name` until it is in `target` and returns its status.
+///
+/// While the service is in a pending state the poll interval is a tenth of its
+/// `wait_hint`. Fails with [`ServiceError::Timeout`] when the checkpoint does not
+/// advance within one `wait_hint`, or when `timeout` has passed, and right away
+/// when the service settles in a state other than `target`.
+pub fn wait_for_state(
+    ctl: &dyn ServiceController,
+    service_name: &str,
+    target: ServiceState,
+    timeout: Duration,
+) -> Result<crate::scm::ServiceStatus> {
+    poll_state(ctl, service_name, target, Some(timeout))
+}
+
+/// Like [`wait_for_state`], but for as long as the service keeps advancing its
+/// checkpoint, however long that takes in total. A service that reports no
+/// `wait_hint` has to advance within [`STATE_TIMEOUT`].
+pub fn wait_for_progress(
+    ctl: &dyn ServiceController,
+    service_name: &str,
+    target: ServiceState,
+) -> Result<crate::scm::ServiceStatus> {
+    poll_state(ctl, service_name, target, None)
+}
+
+fn poll_state(
+    ctl: &dyn ServiceController,
+    service_name: &str,
+    target: ServiceState,
+    timeout: Option<Duration>,
+) -> Result<crate::scm::ServiceStatus> {
     let start = Instant::now();
-    let timeout = Duration::from_secs(5);
-    while start.elapsed() < timeout {
-        if let Err(windows_service::Error::Winapi(e)) =
-            service_manager.open_service(service_name, ServiceAccess::QUERY_STATUS)
-        {
-            if e.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST as i32) {
-                warn!("service deleted");
-                return Ok(());
-            }
+    let mut status = ctl.query_status(service_name)?;
+    let mut progress = Instant::now();
+    while status.current_state != target {
+        if !is_pending(status.current_state) {
+            return Err(ServiceError::Other(io::Error::other(format!(
+                "{} is {:?}, expected {:?}",
+                service_name, status.current_state, target
+            ))));
+        }
+        let stall_after = match (status.wait_hint.is_zero(), timeout) {
+            (false, _) => Some(status.wait_hint),
+            (true, None) => Some(STATE_TIMEOUT),
+            (true, Some(_)) => None,
+        };
+        let stalled = stall_after.is_some_and(|hint| progress.elapsed() > hint);
+        let timed_out = timeout.is_some_and(|timeout| start.elapsed() >= timeout);
+        if stalled || timed_out {
+            warn!("{} still {:?} after {:?}", service_name, status.current_state, start.elapsed());
+            return Err(ServiceError::Timeout);
+        }
+
+        let mut interval = (status.wait_hint / 10).clamp(MIN_POLL_INTERVAL, MAX_POLL_INTERVAL);
+        if let Some(timeout) = timeout {
+            interval = interval.min(timeout.saturating_sub(start.elapsed()));
+        }
+        std::thread::sleep(interval);
+
+        let checkpoint = status.checkpoint;
+        status = ctl.query_status(service_name)?;
+        if status.checkpoint != checkpoint || status.current_state == target {
+            progress = Instant::now();
         }
-        std::thread::sleep(Duration::from_secs(1));
     }
-    Ok(())
+    Ok(status)
+}
+
+fn is_pending(state: ServiceState) -> bool {
+    matches!(
+        state,
+        ServiceState::StartPending
+            | ServiceState::StopPending
+            | ServiceState::ContinuePending
+            | ServiceState::PausePending
+    )
 }
 
-pub fn stop(service_name: &str) -> Result<()> {
-    if !service_exist(service_name) {
-        return Err(anyhow!("Service is no exist"));
+pub fn install(ctl: &dyn ServiceController, service_info: ServiceSpec) -> Result<()> {
+    match service_exist(ctl, &service_info.name.to_string_lossy()) {
+        Ok(()) => return Err(ServiceError::AlreadyExists),
+        Err(ServiceError::NotFound) => {}
+        Err(e) => return Err(e),
     }
-    let manager_access = ServiceManagerAccess::CONNECT;
-    let service_manager = match ServiceManager::local_computer(None::<&str>, manager_access) {
-        Ok(sm) => sm,
-        Err(_e) => return Err(anyhow!("Failed to connect to service manager")),
-    };
 
-    let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP;
-    let service = match service_manager.open_service(service_name, service_access) {
-        Ok(s) => s,
-        Err(_e) => return Err(anyhow!("Failed to open service")),
-    };
-    let service_status = match service.query_status() {
-        Ok(s) => s,
-        Err(_e) => return Err(anyhow!("Failed to query service status")),
-    };
+    ctl.create(&service_info)?;
+
+    info!("service installed");
+    Ok(())
+}
+
+pub fn uninstall(ctl: &dyn ServiceController, service_name: &str) -> Result<()> {
+    service_exist(ctl, service_name)?;
+
+    let service_status = ctl.query_status(service_name)?;
     if service_status.current_state != ServiceState::Stopped {
-        if let Ok(_s) = service.stop() {
+        if let Ok(_s) = ctl.stop(service_name) {
+            wait_for_state(ctl, service_name, ServiceState::Stopped, STATE_TIMEOUT)?;
             info!("Stopped {}", service_name);
         }
-        // Wait for service to stop
-        std::thread::sleep(Duration::from_secs(5));
     }
+    ctl.delete(service_name)?;
+    info!("Deleteing {}", service_name);
+    wait_for_delete(ctl, service_name, STATE_TIMEOUT)?;
+    warn!("service deleted");
     Ok(())
 }
 
-pub fn start(service_name: &str) -> Result<()> {
-    if !service_exist(service_name) {
-        return Err(anyhow!("Service is no exist"));
-    }
-    let manager_access = ServiceManagerAccess::CONNECT;
-    let service_manager = match ServiceManager::local_computer(None::<&str>, manager_access) {
-        Ok(sm) => sm,
-        Err(_e) => return Err(anyhow!("Failed to connect to service manager")),
-    };
-    let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::START;
-    let service = match service_manager.open_service(service_name, service_access) {
-        Ok(s) => s,
-        Err(_e) => return Err(anyhow!("Failed to open service")),
-    };
-    // let service_status = match service.query_status() {
-    //     Ok(s) => s,
-    //     Err(_e) => return Err(anyhow!("Failed to query service status")),
-    // };
-    // if service_status.current_state != ServiceState::Stopped {
-    //     if let Ok(_s) = service.stop() {
-    //         info!("Stopped {}", service_name);
-    //     }
-    // }
-    // match service.start(&[OsStr::new("Started from Rust!")]) {
-    match service.start(Vec::<&str>::new().as_slice()) {
-        Ok(_o) => Ok(()),
-        Err(_e) => Err(anyhow!("Failed to query service status")),
+/// Polls `service_name` until the SCM has dropped it.
+///
+/// A service marked for deletion goes away once it is stopped and the last
+/// handle is closed; polling the status also lets the SCM drop it. Fails with
+/// [`ServiceError::MarkedForDelete`] when it is still there after `timeout`.
+fn wait_for_delete(ctl: &dyn ServiceController, service_name: &str, timeout: Duration) -> Result<()> {
+    let start = Instant::now();
+    let mut interval = MIN_POLL_INTERVAL;
+    loop {
+        match ctl.query_status(service_name) {
+            Err(e) if is_not_found(&e) => return Ok(()),
+            Err(e) => return Err(e.into()),
+            Ok(_) => {}
+        }
+        if start.elapsed() >= timeout {
+            warn!("{} still marked for deletion after {:?}", service_name, start.elapsed());
+            return Err(ServiceError::MarkedForDelete);
+        }
+        std::thread::sleep(interval.min(timeout.saturating_sub(start.elapsed())));
+        interval = (interval * 2).min(MAX_POLL_INTERVAL);
     }
 }
 
-pub fn get_config(service_name: &str) -> anyhow::Result<ServiceConfig> {
-    if !service_exist(service_name) {
-        return Err(anyhow!("Service is no exist"));
+pub fn stop(ctl: &dyn ServiceController, service_name: &str) -> Result<()> {
+    service_exist(ctl, service_name)?;
+
+    let service_status = ctl.query_status(service_name)?;
+    if service_status.current_state != ServiceState::Stopped {
+        ctl.stop(service_name)?;
+        wait_for_state(ctl, service_name, ServiceState::Stopped, STATE_TIMEOUT)?;
+        info!("Stopped {}", service_name);
     }
-    let manager_access = ServiceManagerAccess::CONNECT;
-    let service_manager = match ServiceManager::local_computer(None::<&str>, manager_access) {
-        Ok(sm) => sm,
-        Err(_e) => return Err(anyhow!("Failed to connect to service manager")),
-    };
+    Ok(())
+}
 
-    let service_access = ServiceAccess::QUERY_CONFIG;
-    let service = match service_manager.open_service(service_name, service_access) {
-        Ok(s) => s,
-        Err(_e) => return Err(anyhow!("Failed to open service")),
-    };
-    let service_config = service.query_config()?;
-    // info!("{:#?}", service_config);
-    Ok(service_config)
+pub fn start(ctl: &dyn ServiceController, service_name: &str) -> Result<()> {
+    service_exist(ctl, service_name)?;
+    ctl.start(service_name)?;
+    wait_for_state(ctl, service_name, ServiceState::Running, STATE_TIMEOUT)?;
+    info!("Started {}", service_name);
+    Ok(())
 }
 
-pub fn change_config(service_name: &str, service_info: ServiceInfo) -> anyhow::Result<()> {
-    let manager_access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE;
-    let service_manager = ServiceManager::local_computer(None::<&str>, manager_access)?;
+/// Sends `event` to the running service `service_name`.
+pub fn notify(ctl: &dyn ServiceController, service_name: &str, event: UserEvent) -> Result<()> {
+    service_exist(ctl, service_name)?;
+    ctl.notify(service_name, event.to_raw())?;
+    info!("sent {:?} to {}", event, service_name);
+    Ok(())
+}
 
-    let service_access =
-        ServiceAccess::QUERY_STATUS | ServiceAccess::CHANGE_CONFIG | ServiceAccess::STOP;
-    let service = service_manager.open_service(service_name, service_access)?;
+pub fn get_config(ctl: &dyn ServiceController, service_name: &str) -> Result<ServiceConfig> {
+    service_exist(ctl, service_name)?;
 
+    let service_config = ctl.query_config(service_name)?;
+    // info!("{:#?}", service_config);
+    Ok(service_config)
+}
+
+pub fn change_config(
+    ctl: &dyn ServiceController,
+    service_name: &str,
+    service_info: ServiceSpec,
+) -> Result<()> {
     info!("change {} service", service_name);
     info!("stopping {} service", service_name);
 
-    let service_status = service.query_status()?;
+    let service_status = ctl.query_status(service_name)?;
     if service_status.current_state == ServiceState::Running {
         // stop service
-        service.stop().ok();
-        // wait for service to stop
-        loop {
-            let _state = service.query_status()?;
-            // info!("_state:{:#?}", _state);
-            if _state.current_state == ServiceState::Stopped {
-                info!("{} service stopped", service_name);
-                break;
-            }
-
-            std::thread::sleep(Duration::from_millis(250));
-        }
+        ctl.stop(service_name).ok();
+        wait_for_state(ctl, service_name, ServiceState::Stopped, STATE_TIMEOUT)?;
+        info!("{} service stopped", service_name);
     }
 
     info!("patching {} service", service_name);
     // service_info.executable_path = service_binary_path;
-    service.change_config(&service_info)?;
+    ctl.change_config(service_name, &service_info)?;
 
     info!("successfully patched {} service", service_name);
 
     // info!("starting {} service", service_name);
-    // service.start(Vec::<&str>::new().as_slice())?;
+    // ctl.start(service_name)?;
     // info!("started {} service", service_name);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::*;
+    use crate::scm::{
+        FailureActions, FakeOp, FakeServiceController, ServiceOptions, SidType, StartType,
+        ERROR_ACCESS_DENIED, ERROR_SERVICE_DISABLED, ERROR_SERVICE_REQUEST_TIMEOUT,
+        RPC_S_SERVER_UNAVAILABLE, SERVICE_ERROR_NORMAL, SERVICE_WIN32_OWN_PROCESS,
+    };
+
+    const NAME: &str = "TestSvc";
+
+    fn spec(name: &str) -> ServiceSpec {
+        ServiceSpec {
+            name: OsString::from(name),
+            display_name: OsString::from(name),
+            service_type: SERVICE_WIN32_OWN_PROCESS,
+            start_type: StartType::AutoStart,
+            error_control: SERVICE_ERROR_NORMAL,
+            executable_path: r"C:\Program Files\Test\test.exe".into(),
+            launch_arguments: vec![OsString::from("run")],
+            dependencies: vec![],
+            account_name: None,
+            account_password: None,
+            options: ServiceOptions::default(),
+        }
+    }
+
+    #[test]
+    fn install_creates_a_stopped_service() {
+        let ctl = FakeServiceController::new();
+        install(&ctl, spec(NAME)).unwrap();
+        assert_eq!(ctl.state(NAME), Some(ServiceState::Stopped));
+        assert_eq!(ctl.start_type(NAME), Some(StartType::AutoStart));
+    }
+
+    fn options() -> ServiceOptions {
+        ServiceOptions {
+            description: Some(OsString::from("test service")),
+            delayed_auto_start: Some(true),
+            failure_actions: Some(FailureActions {
+                restart_delays: vec![Duration::from_secs(5)],
+                reset_period: Some(Duration::from_secs(60)),
+            }),
+            failure_actions_on_non_crash: Some(true),
+            sid_type: Some(SidType::Unrestricted),
+        }
+    }
+
+    #[test]
+    fn install_applies_the_service_options() {
+        let ctl = FakeServiceController::new();
+        install(
+            &ctl,
+            ServiceSpec {
+                options: options(),
+                ..spec(NAME)
+            },
+        )
+        .unwrap();
+        assert_eq!(ctl.options(NAME), Some(options()));
+    }
+
+    #[test]
+    fn reinstall_starts_from_fresh_options() {
+        let ctl = FakeServiceController::new();
+        install(
+            &ctl,
+            ServiceSpec {
+                options: options(),
+                ..spec(NAME)
+            },
+        )
+        .unwrap();
+        uninstall(&ctl, NAME).unwrap();
+
+        let description = ServiceOptions {
+            description: Some(OsString::from("test service")),
+            ..ServiceOptions::default()
+        };
+        install(
+            &ctl,
+            ServiceSpec {
+                options: description.clone(),
+                ..spec(NAME)
+            },
+        )
+        .unwrap();
+        assert_eq!(ctl.options(NAME), Some(description));
+    }
+
+    #[test]
+    fn change_config_keeps_the_options_it_does_not_set() {
+        let ctl = FakeServiceController::new();
+        install(
+            &ctl,
+            ServiceSpec {
+                options: options(),
+                ..spec(NAME)
+            },
+        )
+        .unwrap();
+
+        // What `reconfigure` sends: no options at all.
+        change_config(&ctl, NAME, spec(NAME)).unwrap();
+        assert_eq!(ctl.options(NAME), Some(options()));
+
+        let restricted = ServiceOptions {
+            sid_type: Some(SidType::Restricted),
+            ..ServiceOptions::default()
+        };
+        change_config(
+            &ctl,
+            NAME,
+            ServiceSpec {
+                options: restricted,
+                ..spec(NAME)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            ctl.options(NAME),
+            Some(ServiceOptions {
+                sid_type: Some(SidType::Restricted),
+                ..options()
+            })
+        );
+    }
+
+    #[test]
+    fn install_over_an_existing_service_fails() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::OnDemand, ServiceState::Running);
+        let err = install(&ctl, spec(NAME)).unwrap_err();
+        assert!(matches!(err, ServiceError::AlreadyExists));
+        assert_eq!(err.exit_code(), 12);
+        // The existing service is left alone.
+        assert_eq!(ctl.start_type(NAME), Some(StartType::OnDemand));
+    }
+
+    #[test]
+    fn uninstall_stops_a_running_service_first() {
+        let ctl = FakeServiceController::new().with_pending_polls(3);
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        uninstall(&ctl, NAME).unwrap();
+        assert!(!ctl.contains(NAME));
+    }
+
+    #[test]
+    fn uninstall_of_a_missing_service_is_not_found() {
+        let ctl = FakeServiceController::new();
+        let err = uninstall(&ctl, NAME).unwrap_err();
+        assert!(matches!(err, ServiceError::NotFound));
+        assert_eq!(err.exit_code(), 10);
+    }
+
+    #[test]
+    fn delete_of_a_service_that_keeps_running_is_marked_for_delete() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        ctl.delete(NAME).unwrap();
+        let err = wait_for_delete(&ctl, NAME, Duration::from_millis(300)).unwrap_err();
+        assert!(matches!(err, ServiceError::MarkedForDelete));
+        assert_eq!(err.exit_code(), 14);
+        assert!(ctl.contains(NAME));
+    }
+
+    #[test]
+    fn stop_waits_for_the_pending_state() {
+        let ctl = FakeServiceController::new().with_pending_polls(3);
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        stop(&ctl, NAME).unwrap();
+        assert_eq!(ctl.state(NAME), Some(ServiceState::Stopped));
+    }
+
+    #[test]
+    fn stop_times_out_when_the_service_does_not_respond() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        ctl.fail_next(FakeOp::Stop, NAME, ERROR_SERVICE_REQUEST_TIMEOUT);
+        let err = stop(&ctl, NAME).unwrap_err();
+        assert!(matches!(err, ServiceError::Timeout));
+        assert_eq!(err.exit_code(), 13);
+        assert_eq!(ctl.state(NAME), Some(ServiceState::Running));
+    }
+
+    #[test]
+    fn wait_for_state_times_out_while_still_pending() {
+        let ctl = FakeServiceController::new().with_pending_polls(1000);
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        ctl.stop(NAME).unwrap();
+        let err = wait_for_state(
+            &ctl,
+            NAME,
+            ServiceState::Stopped,
+            Duration::from_millis(300),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ServiceError::Timeout));
+        assert_eq!(ctl.state(NAME), Some(ServiceState::StopPending));
+    }
+
+    #[test]
+    fn wait_for_progress_outlasts_the_timeout_while_the_checkpoint_advances() {
+        let ctl = FakeServiceController::new().with_pending_polls(5);
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Stopped);
+        ctl.start(NAME).unwrap();
+        let err = wait_for_state(
+            &ctl,
+            NAME,
+            ServiceState::Running,
+            Duration::from_millis(200),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ServiceError::Timeout));
+
+        let status = wait_for_progress(&ctl, NAME, ServiceState::Running).unwrap();
+        assert_eq!(status.current_state, ServiceState::Running);
+    }
+
+    #[test]
+    fn wait_for_progress_fails_when_settled_elsewhere() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Stopped);
+        let err = wait_for_progress(&ctl, NAME, ServiceState::Running).unwrap_err();
+        assert!(matches!(err, ServiceError::Other(_)));
+    }
+
+    #[test]
+    fn wait_for_state_fails_when_settled_elsewhere() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Stopped);
+        let err = wait_for_state(&ctl, NAME, ServiceState::Running, STATE_TIMEOUT).unwrap_err();
+        assert!(matches!(err, ServiceError::Other(_)));
+        assert_eq!(err.exit_code(), 16);
+    }
+
+    #[test]
+    fn start_of_a_disabled_service_keeps_the_win32_code() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::Disabled, ServiceState::Stopped);
+        let err = start(&ctl, NAME).unwrap_err();
+        assert!(matches!(err, ServiceError::Os(ERROR_SERVICE_DISABLED)));
+        assert_eq!(err.code(), Some(ERROR_SERVICE_DISABLED));
+        assert_eq!(err.exit_code(), 15);
+    }
+
+    #[test]
+    fn access_denied_and_unreachable_are_reported() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Stopped);
+
+        ctl.fail_next(FakeOp::Open, NAME, ERROR_ACCESS_DENIED);
+        let err = start(&ctl, NAME).unwrap_err();
+        assert!(matches!(err, ServiceError::AccessDenied));
+        assert_eq!(err.exit_code(), 11);
+
+        ctl.fail_next(FakeOp::Open, NAME, RPC_S_SERVER_UNAVAILABLE);
+        let err = start(&ctl, NAME).unwrap_err();
+        assert!(matches!(
+            err,
+            ServiceError::Unreachable(RPC_S_SERVER_UNAVAILABLE)
+        ));
+        assert_eq!(err.exit_code(), 17);
+    }
+
+    #[test]
+    fn change_config_stops_the_service_and_reconfigures_it() {
+        let ctl = FakeServiceController::new().with_pending_polls(2);
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        let mut spec = spec(NAME);
+        spec.start_type = StartType::Disabled;
+        change_config(&ctl, NAME, spec).unwrap();
+        assert_eq!(ctl.state(NAME), Some(ServiceState::Stopped));
+        assert_eq!(ctl.start_type(NAME), Some(StartType::Disabled));
+    }
+
+    #[test]
+    fn notify_delivers_user_events() {
+        let ctl = FakeServiceController::new();
+        ctl.insert(NAME, StartType::AutoStart, ServiceState::Running);
+        notify(&ctl, NAME, UserEvent::ReloadConfig).unwrap();
+        assert_eq!(
+            ctl.notifications(NAME),
+            vec![UserEvent::ReloadConfig.to_raw()]
+        );
+    }
+}
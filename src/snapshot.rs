@@ -0,0 +1,463 @@
+//! Original system state, recorded before the blocker first changes a target.
+//!
+//! The snapshot is a JSON file in the install directory. Each target is recorded
+//! once, the first time a rule is about to change it, and is never overwritten
+//! afterwards, so `restore` always goes back to the state before the blocker.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use own_logger::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::RegistryData,
+    deploy::InstallDir,
+    kill_update,
+    policy::{CycleReport, RuleReport, RuleStatus},
+    registry::Hive,
+    rules::Host,
+    scm::{is_not_found, StartType},
+    tasks::TaskState,
+};
+
+pub const SNAPSHOT_FILE_NAME: &str = "snapshot.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceSnapshot {
+    pub name: String,
+    pub start_type: StartType,
+    /// Full command line as stored in `ImagePath`.
+    pub image_path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistrySnapshot {
+    pub hive: Hive,
+    pub key: String,
+    pub name: String,
+    /// `None` when the value did not exist.
+    pub value: Option<RegistryData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskSnapshot {
+    pub path: String,
+    /// `None` when the task did not exist.
+    pub state: Option<TaskState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub services: BTreeMap<String, ServiceSnapshot>,
+    pub registry: BTreeMap<String, RegistrySnapshot>,
+    pub tasks: BTreeMap<String, TaskSnapshot>,
+
+    /// Where the snapshot is persisted, `None` keeps it in memory only.
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Snapshot {
+    /// Default snapshot location: the install directory, see [`InstallDir::local`].
+    pub fn default_path() -> PathBuf {
+        InstallDir::local().snapshot()
+    }
+
+    /// A snapshot that is never written to disk.
+    pub fn memory() -> Self {
+        Self::default()
+    }
+
+    /// Loads the snapshot at `path`, starting an empty one when the file does not exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut snapshot = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("Invalid snapshot file {}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read snapshot file {}", path.display()))
+            }
+        };
+        snapshot.path = Some(path.to_path_buf());
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot through a temporary file so a crash never leaves it half written.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write snapshot file {}", tmp.display()))?;
+        fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to write snapshot file {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Deletes the snapshot file once everything has been restored.
+    pub fn remove(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty() && self.registry.is_empty() && self.tasks.is_empty()
+    }
+
+    pub fn registry_key(hive: Hive, key: &str, name: &str) -> String {
+        format!("{}\\{}\\{}", hive, key, name).to_ascii_lowercase()
+    }
+
+    /// Puts every recorded target back into its original state.
+    pub fn restore(&self, host: &Host) -> CycleReport {
+        self.restore_with(host, true)
+    }
+
+    /// Reports what [`Snapshot::restore`] would change, without changing anything.
+    ///
+    /// Targets that would be restored are reported as [`RuleStatus::Fixed`].
+    pub fn plan_restore(&self, host: &Host) -> CycleReport {
+        self.restore_with(host, false)
+    }
+
+    /// Puts the service `name` back into its recorded state; nothing was
+    /// changed, and nothing is to do, when it was never recorded.
+    pub fn restore_service(&self, host: &Host, name: &str) -> RuleStatus {
+        match self.services.get(&name.to_ascii_lowercase()) {
+            Some(service) => Self::restore_service_to(host, service, true),
+            None => RuleStatus::Compliant,
+        }
+    }
+
+    /// Like [`Snapshot::restore_service`], for a registry value.
+    pub fn restore_value(&self, host: &Host, hive: Hive, key: &str, name: &str) -> RuleStatus {
+        match self.registry.get(&Self::registry_key(hive, key, name)) {
+            Some(value) => Self::restore_value_to(host, value, true),
+            None => RuleStatus::Compliant,
+        }
+    }
+
+    /// Like [`Snapshot::restore_service`], for the scheduled task at `path`.
+    pub fn restore_task(&self, host: &Host, path: &str) -> RuleStatus {
+        match self.tasks.get(&path.to_ascii_lowercase()) {
+            Some(task) => Self::restore_task_to(host, task, true),
+            None => RuleStatus::Compliant,
+        }
+    }
+
+    fn restore_with(&self, host: &Host, apply: bool) -> CycleReport {
+        let mut rules = vec![];
+        for service in self.services.values() {
+            rules.push(RuleReport::new(
+                format!("service:{}", service.name),
+                Self::restore_service_to(host, service, apply),
+            ));
+        }
+        for value in self.registry.values() {
+            rules.push(RuleReport::new(
+                format!("registry:{}\\{}\\{}", value.hive, value.key, value.name),
+                Self::restore_value_to(host, value, apply),
+            ));
+        }
+        for task in self.tasks.values() {
+            rules.push(RuleReport::new(
+                format!("task:{}", task.path),
+                Self::restore_task_to(host, task, apply),
+            ));
+        }
+        if apply {
+            for report in &rules {
+                match &report.status {
+                    RuleStatus::Failed(e) => error!("restore {}: {}", report.rule, e),
+                    status => info!("restore {}: {:?}", report.rule, status),
+                }
+            }
+        }
+        CycleReport { rules }
+    }
+
+    fn restore_service_to(host: &Host, service: &ServiceSnapshot, apply: bool) -> RuleStatus {
+        let config = match host.services.query_config(&service.name) {
+            Ok(config) => config,
+            Err(e) if is_not_found(&e) => return RuleStatus::Absent,
+            Err(e) => return RuleStatus::Failed(e.to_string()),
+        };
+        if config.start_type == service.start_type && config.executable_path == service.image_path {
+            return RuleStatus::Compliant;
+        }
+        let observed = format!("{:?}", config.start_type);
+        if !apply {
+            return RuleStatus::Fixed { observed };
+        }
+        match kill_update::reconfigure(
+            host.services.as_ref(),
+            &service.name,
+            service.start_type,
+            Some(&service.image_path),
+        ) {
+            Ok(()) => RuleStatus::Fixed { observed },
+            Err(e) => RuleStatus::Failed(format!("{:#}", e)),
+        }
+    }
+
+    fn restore_value_to(host: &Host, value: &RegistrySnapshot, apply: bool) -> RuleStatus {
+        let current = match host.registry.get(value.hive, &value.key, &value.name) {
+            Ok(current) => current,
+            Err(e) => return RuleStatus::Failed(e.to_string()),
+        };
+        if current == value.value {
+            return RuleStatus::Compliant;
+        }
+        let observed = format!("{:?}", current);
+        if !apply {
+            return RuleStatus::Fixed { observed };
+        }
+        let result = match &value.value {
+            Some(data) => host.registry.set(value.hive, &value.key, &value.name, data),
+            None => host.registry.delete(value.hive, &value.key, &value.name),
+        };
+        match result {
+            Ok(()) => RuleStatus::Fixed { observed },
+            Err(e) => RuleStatus::Failed(e.to_string()),
+        }
+    }
+
+    fn restore_task_to(host: &Host, task: &TaskSnapshot, apply: bool) -> RuleStatus {
+        let original = match task.state {
+            Some(state) => state,
+            None => return RuleStatus::Compliant,
+        };
+        let current = match host.tasks.state(&task.path) {
+            Ok(Some(current)) => current,
+            Ok(None) => return RuleStatus::Absent,
+            Err(e) => return RuleStatus::Failed(e.to_string()),
+        };
+        if current == original {
+            return RuleStatus::Compliant;
+        }
+        let observed = format!("{:?}", current);
+        if !apply {
+            return RuleStatus::Fixed { observed };
+        }
+        match host
+            .tasks
+            .set_enabled(&task.path, original == TaskState::Enabled)
+        {
+            Ok(()) => RuleStatus::Fixed { observed },
+            Err(e) => RuleStatus::Failed(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        policy::Policy,
+        registry::{MemoryHive, RegistryStore},
+        scm::{FakeOp, FakeServiceController, ServiceController, ServiceState},
+        tasks::{FakeTaskScheduler, TaskScheduler},
+    };
+
+    const TASK: &str = r#"\Microsoft\Windows\WindowsUpdate\Scheduled Start"#;
+    const UX_KEY: &str = r#"Software\Microsoft\WindowsUpdate\UX\Settings"#;
+    const MEDIC_KEY: &str = r#"SYSTEM\ControlSet001\Services\WaaSMedicSvc"#;
+
+    struct Fakes {
+        host: Host,
+        services: Arc<FakeServiceController>,
+        registry: Arc<MemoryHive>,
+        tasks: Arc<FakeTaskScheduler>,
+    }
+
+    /// Windows Update enabled and running, `UxOption` never set.
+    fn fakes() -> Fakes {
+        let services = Arc::new(FakeServiceController::new());
+        services.insert("wuauserv", StartType::OnDemand, ServiceState::Running);
+        services.insert("UsoSvc", StartType::AutoStart, ServiceState::Running);
+        let registry = Arc::new(MemoryHive::new());
+        registry.insert(
+            Hive::LocalMachine,
+            MEDIC_KEY,
+            "Start",
+            RegistryData::Dword(3),
+        );
+        let tasks = Arc::new(FakeTaskScheduler::new());
+        tasks.insert(TASK, TaskState::Enabled);
+        let host = Host {
+            services: services.clone(),
+            registry: registry.clone(),
+            tasks: tasks.clone(),
+            ..Host::memory()
+        };
+        Fakes {
+            host,
+            services,
+            registry,
+            tasks,
+        }
+    }
+
+    /// Runs one block mode cycle on `fakes`, recording into a fresh snapshot.
+    fn block(fakes: &Fakes) -> Snapshot {
+        let mut snapshot = Snapshot::memory();
+        let report = Policy::from_config(&Config::default()).evaluate(&fakes.host, &mut snapshot);
+        assert_eq!(report.failed(), 0, "{:#?}", report);
+        snapshot
+    }
+
+    #[test]
+    fn restore_puts_back_what_the_policy_changed() {
+        let fakes = fakes();
+        let before = fakes.services.query_config("wuauserv").unwrap();
+        let snapshot = block(&fakes);
+        assert_eq!(snapshot.services.len(), 2);
+        assert_eq!(snapshot.registry.len(), 2);
+        assert_eq!(snapshot.tasks.len(), 1);
+
+        let report = snapshot.restore(&fakes.host);
+        assert_eq!(report.failed(), 0, "{:#?}", report);
+        assert_eq!(report.fixed(), 5);
+
+        let after = fakes.services.query_config("wuauserv").unwrap();
+        assert_eq!(after.start_type, StartType::OnDemand);
+        assert_eq!(after.executable_path, before.executable_path);
+        assert_eq!(
+            fakes.services.start_type("UsoSvc"),
+            Some(StartType::AutoStart)
+        );
+        // Restoring never starts a service, Windows does when it needs it.
+        assert_eq!(fakes.services.state("UsoSvc"), Some(ServiceState::Stopped));
+        assert_eq!(
+            fakes
+                .registry
+                .get(Hive::LocalMachine, MEDIC_KEY, "Start")
+                .unwrap(),
+            Some(RegistryData::Dword(3))
+        );
+        assert_eq!(
+            fakes
+                .registry
+                .get(Hive::LocalMachine, UX_KEY, "UxOption")
+                .unwrap(),
+            None
+        );
+        assert_eq!(fakes.tasks.state(TASK).unwrap(), Some(TaskState::Enabled));
+
+        let again = snapshot.restore(&fakes.host);
+        assert_eq!(again.compliant(), again.rules.len(), "{:#?}", again);
+    }
+
+    #[test]
+    fn plan_restore_changes_nothing() {
+        let fakes = fakes();
+        let snapshot = block(&fakes);
+        let writes = fakes.registry.writes();
+
+        let plan = snapshot.plan_restore(&fakes.host);
+        assert_eq!(plan.fixed(), 5);
+        assert_eq!(fakes.registry.writes(), writes);
+        assert_eq!(
+            fakes.services.start_type("UsoSvc"),
+            Some(StartType::Disabled)
+        );
+        assert_eq!(fakes.tasks.state(TASK).unwrap(), Some(TaskState::Disabled));
+    }
+
+    #[test]
+    fn rule_revert_and_restore_agree() {
+        let fakes = fakes();
+        let snapshot = block(&fakes);
+        Policy::from_config(&Config::default()).revert(&fakes.host, &snapshot);
+
+        let report = snapshot.plan_restore(&fakes.host);
+        assert_eq!(report.compliant(), report.rules.len(), "{:#?}", report);
+    }
+
+    #[test]
+    fn restore_skips_removed_targets_and_reports_failures() {
+        let fakes = fakes();
+        let snapshot = block(&fakes);
+        fakes.services.delete("wuauserv").unwrap();
+        fakes.services.fail_next(FakeOp::ChangeConfig, "UsoSvc", 5);
+
+        let report = snapshot.restore(&fakes.host);
+        let status = |rule: &str| {
+            report
+                .rules
+                .iter()
+                .find(|r| r.rule == rule)
+                .map(|r| r.status.clone())
+        };
+        assert_eq!(status("service:wuauserv"), Some(RuleStatus::Absent));
+        assert!(matches!(
+            status("service:UsoSvc"),
+            Some(RuleStatus::Failed(e)) if e.contains("access denied")
+        ));
+        // The other targets are restored anyway.
+        assert_eq!(report.fixed(), 3);
+        assert_eq!(fakes.tasks.state(TASK).unwrap(), Some(TaskState::Enabled));
+    }
+
+    #[test]
+    fn a_task_that_did_not_exist_is_left_alone() {
+        let fakes = fakes();
+        let mut snapshot = Snapshot::memory();
+        snapshot.tasks.insert(
+            TASK.to_ascii_lowercase(),
+            TaskSnapshot {
+                path: TASK.to_owned(),
+                state: None,
+            },
+        );
+        assert_eq!(
+            snapshot.restore_task(&fakes.host, TASK),
+            RuleStatus::Compliant
+        );
+        assert_eq!(fakes.tasks.state(TASK).unwrap(), Some(TaskState::Enabled));
+    }
+
+    #[test]
+    fn save_load_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SNAPSHOT_FILE_NAME);
+        let fakes = fakes();
+
+        let mut snapshot = Snapshot::load(&path).unwrap();
+        assert!(snapshot.is_empty());
+        Policy::from_config(&Config::default()).evaluate(&fakes.host, &mut snapshot);
+        assert!(path.exists());
+
+        let loaded = Snapshot::load(&path).unwrap();
+        assert_eq!(loaded, snapshot);
+        loaded.remove().unwrap();
+        assert!(!path.exists());
+        loaded.remove().unwrap();
+    }
+
+    #[test]
+    fn load_rejects_a_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SNAPSHOT_FILE_NAME);
+        fs::write(&path, "{ not json").unwrap();
+        let err = format!("{:#}", Snapshot::load(&path).unwrap_err());
+        assert!(err.starts_with("Invalid snapshot file"), "{}", err);
+    }
+}
@@ -0,0 +1,289 @@
+//! Tamper detection: targets that were compliant and drifted again.
+//!
+//! Every event is appended as one JSON line to a log file in the install
+//! directory. The per-target counts are rebuilt from that file on start, so
+//! they survive service restarts.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use own_logger::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deploy::InstallDir,
+    policy::{CycleReport, RuleStatus},
+    rules::ProcessAbsent,
+};
+
+pub const TAMPER_LOG_FILE_NAME: &str = "tamper.log";
+
+/// A target that was compliant at the end of one cycle and had drifted at the start of the next.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TamperEvent {
+    pub timestamp: DateTime<Utc>,
+    pub target: String,
+    /// The compliant value the blocker left in place.
+    pub previous: String,
+    pub observed: String,
+}
+
+#[derive(Debug, Default)]
+pub struct TamperLog {
+    /// Where events are appended, `None` keeps them in memory only.
+    path: Option<PathBuf>,
+    /// Targets that were compliant at the end of the last observed cycle.
+    compliant: BTreeSet<String>,
+    counts: BTreeMap<String, u64>,
+}
+
+impl TamperLog {
+    /// Default log location: the install directory, see [`InstallDir::local`].
+    pub fn default_path() -> PathBuf {
+        InstallDir::local().tamper_log()
+    }
+
+    /// A log that is never written to disk.
+    pub fn memory() -> Self {
+        Self::default()
+    }
+
+    /// Opens the log at `path` and counts the events already in it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut log = Self {
+            path: Some(path.to_path_buf()),
+            ..Self::default()
+        };
+        for event in Self::read(path)? {
+            *log.counts.entry(event.target).or_default() += 1;
+        }
+        Ok(log)
+    }
+
+    /// Reads every event in the log at `path`, skipping lines that do not parse.
+    pub fn read(path: &Path) -> Result<Vec<TamperEvent>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read tamper log {}", path.display()))
+            }
+        };
+        Ok(text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    warn!("tamper log: skipping invalid line {:?}: {}", line, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Number of tamper events per target.
+    pub fn counts(&self) -> &BTreeMap<String, u64> {
+        &self.counts
+    }
+
+    /// Records a tamper event for every target that was compliant after the
+    /// previous cycle and drifted in `report`, and returns them.
+    ///
+    /// The first cycle only establishes which targets are compliant. Process
+    /// rules are left out: Windows starts those processes again on its own, so
+    /// they would log an event almost every cycle without anyone tampering.
+    pub fn observe(&mut self, report: &CycleReport, now: DateTime<Utc>) -> Vec<TamperEvent> {
+        let mut events = vec![];
+        for rule in &report.rules {
+            if rule.rule.starts_with(ProcessAbsent::ID_PREFIX) {
+                continue;
+            }
+            if let Some(drift) = &rule.drift {
+                if self.compliant.contains(&rule.rule) {
+                    events.push(TamperEvent {
+                        timestamp: now,
+                        target: rule.rule.clone(),
+                        previous: drift.expected.clone(),
+                        observed: drift.observed.clone(),
+                    });
+                }
+            }
+            match rule.status {
+                RuleStatus::Compliant | RuleStatus::Fixed { .. } => {
+                    self.compliant.insert(rule.rule.clone());
+                }
+                RuleStatus::Absent | RuleStatus::Failed(_) => {
+                    self.compliant.remove(&rule.rule);
+                }
+            }
+        }
+
+        for event in &events {
+            let count = self.counts.entry(event.target.clone()).or_default();
+            *count += 1;
+            warn!(
+                "tamper: {} changed from {} to {} ({} times)",
+                event.target, event.previous, event.observed, count
+            );
+            if let Err(e) = self.append(event) {
+                error!("tamper log: {:#}", e);
+            }
+        }
+        events
+    }
+
+    fn append(&self, event: &TamperEvent) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open tamper log {}", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(event)?)
+            .with_context(|| format!("Failed to write tamper log {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::policy::{Drift, RuleReport};
+
+    const SERVICE: &str = "service:wuauserv";
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+    }
+
+    fn compliant(rule: &str) -> RuleReport {
+        RuleReport::new(rule.to_owned(), RuleStatus::Compliant)
+    }
+
+    fn fixed(rule: &str) -> RuleReport {
+        RuleReport {
+            drift: Some(Drift {
+                expected: "Disabled/Stopped".to_owned(),
+                observed: "OnDemand/Running".to_owned(),
+            }),
+            ..RuleReport::new(
+                rule.to_owned(),
+                RuleStatus::Fixed {
+                    observed: "OnDemand/Running".to_owned(),
+                },
+            )
+        }
+    }
+
+    fn cycle(rules: Vec<RuleReport>) -> CycleReport {
+        CycleReport { rules }
+    }
+
+    #[test]
+    fn a_drift_after_a_compliant_cycle_is_tampering() {
+        let mut log = TamperLog::memory();
+        // The first cycle only learns what is compliant.
+        assert_eq!(log.observe(&cycle(vec![fixed(SERVICE)]), now()), vec![]);
+
+        let events = log.observe(&cycle(vec![fixed(SERVICE)]), now());
+        assert_eq!(
+            events,
+            vec![TamperEvent {
+                timestamp: now(),
+                target: SERVICE.to_owned(),
+                previous: "Disabled/Stopped".to_owned(),
+                observed: "OnDemand/Running".to_owned(),
+            }]
+        );
+        log.observe(&cycle(vec![compliant(SERVICE)]), now());
+        log.observe(&cycle(vec![fixed(SERVICE)]), now());
+        assert_eq!(log.counts().get(SERVICE), Some(&2));
+    }
+
+    #[test]
+    fn a_drift_after_a_failed_or_absent_cycle_is_not_tampering() {
+        let mut log = TamperLog::memory();
+        log.observe(&cycle(vec![compliant(SERVICE)]), now());
+        log.observe(
+            &cycle(vec![RuleReport::new(
+                SERVICE.to_owned(),
+                RuleStatus::Failed("access denied".to_owned()),
+            )]),
+            now(),
+        );
+        assert_eq!(log.observe(&cycle(vec![fixed(SERVICE)]), now()), vec![]);
+
+        log.observe(
+            &cycle(vec![RuleReport::new(
+                SERVICE.to_owned(),
+                RuleStatus::Absent,
+            )]),
+            now(),
+        );
+        assert_eq!(log.observe(&cycle(vec![fixed(SERVICE)]), now()), vec![]);
+        assert!(log.counts().is_empty());
+    }
+
+    #[test]
+    fn a_respawned_process_is_not_tampering() {
+        let mut log = TamperLog::memory();
+        let process = format!("{}TiWorker.exe", ProcessAbsent::ID_PREFIX);
+        for _ in 0..3 {
+            assert_eq!(log.observe(&cycle(vec![fixed(&process)]), now()), vec![]);
+        }
+        assert!(log.counts().is_empty());
+    }
+
+    #[test]
+    fn events_are_appended_as_json_lines_and_counted_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(TAMPER_LOG_FILE_NAME);
+        let mut log = TamperLog::open(&path).unwrap();
+        for _ in 0..3 {
+            log.observe(&cycle(vec![fixed(SERVICE)]), now());
+        }
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            concat!(
+                r#"{"timestamp":"2024-05-01T12:00:00Z","target":"service:wuauserv","previous":"Disabled/Stopped","observed":"OnDemand/Running"}"#,
+                "\n",
+                r#"{"timestamp":"2024-05-01T12:00:00Z","target":"service:wuauserv","previous":"Disabled/Stopped","observed":"OnDemand/Running"}"#,
+                "\n",
+            )
+        );
+        assert_eq!(
+            TamperLog::open(&path).unwrap().counts().get(SERVICE),
+            Some(&2)
+        );
+    }
+
+    #[test]
+    fn read_skips_invalid_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(TAMPER_LOG_FILE_NAME);
+        assert_eq!(TamperLog::read(&path).unwrap(), vec![]);
+
+        let mut log = TamperLog::open(&path).unwrap();
+        log.observe(&cycle(vec![fixed(SERVICE)]), now());
+        log.observe(&cycle(vec![fixed(SERVICE)]), now());
+        let mut text = fs::read_to_string(&path).unwrap();
+        text.push_str("not json\n\n");
+        fs::write(&path, text).unwrap();
+
+        assert_eq!(TamperLog::read(&path).unwrap().len(), 1);
+    }
+}
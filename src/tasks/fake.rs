@@ -0,0 +1,50 @@
+use std::{collections::BTreeMap, io, sync::Mutex};
+
+use super::*;
+
+/// In-memory task folder, task paths are case-insensitive.
+#[derive(Debug, Default)]
+pub struct FakeTaskScheduler {
+    tasks: Mutex<BTreeMap<String, TaskState>>,
+}
+
+impl FakeTaskScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, path: &str, state: TaskState) {
+        self.tasks
+            .lock()
+            .unwrap()
+            .insert(path.to_ascii_lowercase(), state);
+    }
+}
+
+impl TaskScheduler for FakeTaskScheduler {
+    fn state(&self, path: &str) -> io::Result<Option<TaskState>> {
+        Ok(self
+            .tasks
+            .lock()
+            .unwrap()
+            .get(&path.to_ascii_lowercase())
+            .copied())
+    }
+
+    fn set_enabled(&self, path: &str, enabled: bool) -> io::Result<()> {
+        match self.tasks.lock().unwrap().get_mut(&path.to_ascii_lowercase()) {
+            Some(state) => {
+                *state = if enabled {
+                    TaskState::Enabled
+                } else {
+                    TaskState::Disabled
+                };
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("task {} does not exist", path),
+            )),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Scheduled task access.
+//!
+//! [`TaskScheduler`] is backed by `schtasks.exe` on Windows ([`WindowsTaskScheduler`])
+//! and by [`FakeTaskScheduler`] in tests.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+mod fake;
+#[cfg(windows)]
+mod windows;
+
+pub use fake::FakeTaskScheduler;
+#[cfg(windows)]
+pub use self::windows::WindowsTaskScheduler;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TaskState {
+    Enabled,
+    Disabled,
+}
+
+pub trait TaskScheduler {
+    /// State of the task at `path` (e.g. `\Microsoft\Windows\WindowsUpdate\Scheduled Start`),
+    /// `None` when no such task exists.
+    fn state(&self, path: &str) -> io::Result<Option<TaskState>>;
+    fn set_enabled(&self, path: &str, enabled: bool) -> io::Result<()>;
+}
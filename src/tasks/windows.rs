@@ -0,0 +1,86 @@
+use std::{
+    env, fs, io,
+    os::windows::process::CommandExt,
+    path::PathBuf,
+    process::Command,
+};
+
+use super::*;
+
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// [`TaskScheduler`] driving `schtasks.exe`.
+///
+/// The state is read from the task XML (`<Settings><Enabled>`) rather than the
+/// status column, which is localized. For the same reason a missing task is
+/// told apart from other failures by its file in the task store, not by the
+/// schtasks message.
+#[derive(Debug, Default, Clone)]
+pub struct WindowsTaskScheduler;
+
+impl WindowsTaskScheduler {
+    fn schtasks(args: &[&str]) -> io::Result<std::process::Output> {
+        Command::new("schtasks.exe")
+            .args(args)
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+    }
+}
+
+impl TaskScheduler for WindowsTaskScheduler {
+    fn state(&self, path: &str) -> io::Result<Option<TaskState>> {
+        let output = Self::schtasks(&["/Query", "/TN", path, "/XML"])?;
+        if !output.status.success() {
+            // schtasks has no distinct exit code for a missing task.
+            return match fs::metadata(task_file(path)) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                _ => Err(io::Error::other(format!(
+                    "{}: {}",
+                    path,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ))),
+            };
+        }
+        let xml = String::from_utf8_lossy(&output.stdout).replace('\0', "");
+        Ok(Some(parse_state(&xml)))
+    }
+
+    fn set_enabled(&self, path: &str, enabled: bool) -> io::Result<()> {
+        let flag = if enabled { "/ENABLE" } else { "/DISABLE" };
+        let output = Self::schtasks(&["/Change", "/TN", path, flag])?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ))
+        }
+    }
+}
+
+/// Where the Task Scheduler keeps the definition of the task at `path`,
+/// `%SystemRoot%\System32\Tasks\<path>`.
+fn task_file(path: &str) -> PathBuf {
+    let root = env::var_os("SystemRoot")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\Windows"));
+    root.join("System32")
+        .join("Tasks")
+        .join(path.trim_start_matches('\\'))
+}
+
+/// A task is enabled unless its `<Settings>` block says `<Enabled>false</Enabled>`.
+fn parse_state(xml: &str) -> TaskState {
+    let settings = xml
+        .split_once("<Settings>")
+        .and_then(|(_, rest)| rest.split_once("</Settings>"))
+        .map(|(settings, _)| settings)
+        .unwrap_or_default();
+    match settings
+        .split_once("<Enabled>")
+        .and_then(|(_, rest)| rest.split_once("</Enabled>"))
+    {
+        Some((value, _)) if value.trim().eq_ignore_ascii_case("false") => TaskState::Disabled,
+        _ => TaskState::Enabled,
+    }
+}
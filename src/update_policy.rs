@@ -0,0 +1,744 @@
+//! Windows Update Group Policy settings.
+//!
+//! Typed settings for the policy values Windows Update reads, the same ones the
+//! Group Policy editor writes. Each set field becomes a registry value that is
+//! enforced by a [`crate::rules::RegistryValue`] rule, so the policy works
+//! against any [`crate::registry::RegistryStore`].
+
+use std::{fmt, io, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{RegistryData, RegistryValue},
+    registry::{Hive, RegistryStore},
+};
+
+/// Windows Update policy key.
+pub const WU_KEY: &str = r#"HKLM\SOFTWARE\Policies\Microsoft\Windows\WindowsUpdate"#;
+/// Automatic Updates policy key.
+pub const AU_KEY: &str = r#"HKLM\SOFTWARE\Policies\Microsoft\Windows\WindowsUpdate\AU"#;
+/// Device installation restriction policy key.
+pub const DEVICE_RESTRICTIONS_KEY: &str =
+    r#"HKLM\SOFTWARE\Policies\Microsoft\Windows\DeviceInstall\Restrictions"#;
+/// Where Windows records the installed release, below `HKLM`.
+pub const CURRENT_VERSION_KEY: &str = r#"SOFTWARE\Microsoft\Windows NT\CurrentVersion"#;
+
+/// First build number of Windows 11.
+const WINDOWS_11_BUILD: u32 = 22000;
+
+/// `AUOptions`: how updates are downloaded and installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuOption {
+    /// Notify before downloading.
+    NotifyDownload,
+    /// Download automatically, notify before installing.
+    AutoDownloadNotifyInstall,
+    /// Download automatically and install at the scheduled day and time.
+    AutoDownloadScheduleInstall,
+    /// Let local administrators choose in the Settings app.
+    LocalAdminChooses,
+    /// Notify before installing and before restarting.
+    NotifyInstallNotifyRestart,
+}
+
+impl AuOption {
+    pub fn to_raw(self) -> u32 {
+        match self {
+            AuOption::NotifyDownload => 2,
+            AuOption::AutoDownloadNotifyInstall => 3,
+            AuOption::AutoDownloadScheduleInstall => 4,
+            AuOption::LocalAdminChooses => 5,
+            AuOption::NotifyInstallNotifyRestart => 7,
+        }
+    }
+}
+
+/// `ScheduledInstallDay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallDay {
+    EveryDay,
+    Sunday,
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+}
+
+impl InstallDay {
+    pub fn to_raw(self) -> u32 {
+        self as u32
+    }
+}
+
+/// `ProductVersion`: the Windows product a release belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Product {
+    #[serde(rename = "windows-10")]
+    Windows10,
+    #[serde(rename = "windows-11")]
+    Windows11,
+}
+
+impl Product {
+    /// The name Windows Update expects in `ProductVersion`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Product::Windows10 => "Windows 10",
+            Product::Windows11 => "Windows 11",
+        }
+    }
+
+    pub fn from_build(build: u32) -> Self {
+        if build >= WINDOWS_11_BUILD {
+            Product::Windows11
+        } else {
+            Product::Windows10
+        }
+    }
+}
+
+/// A feature update release, `22H2` or the older year-month form `1909`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ReleaseVersion {
+    text: String,
+    /// Two-digit year and half of the year, for ordering.
+    year: u32,
+    half: u32,
+}
+
+impl ReleaseVersion {
+    /// Whether `self` was released after `other`; `2004` and `20H1` are the same release.
+    pub fn is_newer_than(&self, other: &ReleaseVersion) -> bool {
+        (self.year, self.half) > (other.year, other.half)
+    }
+}
+
+impl FromStr for ReleaseVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid release version {:?}, expected e.g. 22H2 or 1909",
+                s
+            )
+        };
+        let bytes = s.as_bytes();
+        if bytes.len() != 4 || !bytes[..2].iter().all(u8::is_ascii_digit) {
+            return Err(invalid());
+        }
+        let half = match &bytes[2..] {
+            b"H1" => 1,
+            b"H2" => 2,
+            month if month.iter().all(u8::is_ascii_digit) => match s[2..].parse() {
+                Ok(1..=6) => 1,
+                Ok(7..=12) => 2,
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            text: s.to_owned(),
+            year: s[..2].parse().map_err(|_| invalid())?,
+            half,
+        })
+    }
+}
+
+impl TryFrom<String> for ReleaseVersion {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ReleaseVersion> for String {
+    fn from(version: ReleaseVersion) -> Self {
+        version.text
+    }
+}
+
+impl fmt::Display for ReleaseVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Stay on a feature update release (`TargetReleaseVersion`), e.g.
+/// `{ product = "windows-11", version = "23H2" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetRelease {
+    pub product: Product,
+    pub version: ReleaseVersion,
+}
+
+impl TargetRelease {
+    /// Whether the system already runs a later release than the pin, which
+    /// Windows Update does not roll back.
+    pub fn is_behind(&self, product: Product, version: &ReleaseVersion) -> bool {
+        product > self.product || (product == self.product && version.is_newer_than(&self.version))
+    }
+}
+
+impl fmt::Display for TargetRelease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.product.name(), self.version)
+    }
+}
+
+/// The release the system runs, `None` when Windows does not record one.
+pub fn running_release(
+    registry: &dyn RegistryStore,
+) -> io::Result<Option<(Product, ReleaseVersion)>> {
+    let get = |name: &str| registry.get(Hive::LocalMachine, CURRENT_VERSION_KEY, name);
+    let build = match get("CurrentBuild")? {
+        Some(RegistryData::String(build)) => build.parse().ok(),
+        _ => None,
+    };
+    // `DisplayVersion` (20H2 and later) replaced `ReleaseId`, which stopped at 2009.
+    let version = match get("DisplayVersion")? {
+        Some(version) => Some(version),
+        None => get("ReleaseId")?,
+    };
+    Ok(match (build, version) {
+        (Some(build), Some(RegistryData::String(version))) => version
+            .parse()
+            .ok()
+            .map(|version| (Product::from_build(build), version)),
+        _ => None,
+    })
+}
+
+/// Offer updates only some days after their release (`DeferQualityUpdates`,
+/// `DeferFeatureUpdates`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeferUpdates {
+    /// Days to defer quality (monthly) updates, 0-30.
+    pub quality_days: u32,
+    /// Days to defer feature updates, 0-365.
+    pub feature_days: u32,
+}
+
+impl Default for DeferUpdates {
+    fn default() -> Self {
+        Self {
+            quality_days: 7,
+            feature_days: 365,
+        }
+    }
+}
+
+impl DeferUpdates {
+    pub fn validate(&self) -> Result<()> {
+        if self.quality_days > 30 {
+            return Err(anyhow!(
+                "defer_updates: quality_days must be at most 30, got {}",
+                self.quality_days
+            ));
+        }
+        if self.feature_days > 365 {
+            return Err(anyhow!(
+                "defer_updates: feature_days must be at most 365, got {}",
+                self.feature_days
+            ));
+        }
+        Ok(())
+    }
+
+    /// The policy values turning both deferrals on.
+    pub fn registry_values(&self) -> Vec<RegistryValue> {
+        [
+            ("DeferQualityUpdates", 1),
+            ("DeferQualityUpdatesPeriodInDays", self.quality_days),
+            ("DeferFeatureUpdates", 1),
+            ("DeferFeatureUpdatesPeriodInDays", self.feature_days),
+        ]
+        .into_iter()
+        .map(|(name, data)| RegistryValue {
+            key: WU_KEY.to_owned(),
+            name: name.to_owned(),
+            value: RegistryData::Dword(data),
+        })
+        .collect()
+    }
+}
+
+/// Keep drivers from changing: no drivers through Windows Update, and
+/// optionally no driver installs at all for some devices.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DriverPolicy {
+    /// `ExcludeWUDriversInQualityUpdate`: leave drivers out of quality updates.
+    pub exclude_from_windows_update: Option<bool>,
+    /// Hardware or compatible IDs (e.g. `PCI\VEN_8086&DEV_15B8`) whose devices may not
+    /// have drivers installed or updated (`DenyDeviceIDs`).
+    pub deny_device_ids: Vec<String>,
+    /// Device setup class GUIDs (e.g. `{4d36e968-e325-11ce-bfc1-08002be10318}`)
+    /// whose devices may not have drivers installed or updated (`DenyDeviceClasses`).
+    pub deny_device_classes: Vec<String>,
+}
+
+impl DriverPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(id) = self
+            .deny_device_ids
+            .iter()
+            .find(|x| x.trim().is_empty() || x.chars().any(char::is_control))
+        {
+            return Err(anyhow!("drivers: invalid device ID {:?}", id));
+        }
+        if let Some(class) = self.deny_device_classes.iter().find(|x| !is_class_guid(x)) {
+            return Err(anyhow!(
+                "drivers: device class {:?} must be a GUID like {{4d36e968-e325-11ce-bfc1-08002be10318}}",
+                class
+            ));
+        }
+        Ok(())
+    }
+
+    /// The registry values the policy enforces.
+    ///
+    /// Each deny list is written as the numbered values `1`, `2`, ... of its
+    /// own subkey, see [`DriverPolicy::numbered_lists`] for the entries a
+    /// longer list left behind.
+    pub fn registry_values(&self) -> Vec<RegistryValue> {
+        let mut values = vec![];
+        if let Some(exclude) = self.exclude_from_windows_update {
+            values.push(RegistryValue {
+                key: WU_KEY.to_owned(),
+                name: "ExcludeWUDriversInQualityUpdate".to_owned(),
+                value: RegistryData::Dword(exclude.into()),
+            });
+        }
+        for (name, list) in [
+            ("DenyDeviceIDs", &self.deny_device_ids),
+            ("DenyDeviceClasses", &self.deny_device_classes),
+        ] {
+            if list.is_empty() {
+                continue;
+            }
+            values.push(RegistryValue {
+                key: DEVICE_RESTRICTIONS_KEY.to_owned(),
+                name: name.to_owned(),
+                value: RegistryData::Dword(1),
+            });
+            for (i, entry) in list.iter().enumerate() {
+                values.push(RegistryValue {
+                    key: format!("{}\\{}", DEVICE_RESTRICTIONS_KEY, name),
+                    name: (i + 1).to_string(),
+                    value: RegistryData::String(entry.clone()),
+                });
+            }
+        }
+        values
+    }
+
+    /// The deny lists the policy writes, whose entries past the end of the
+    /// list are deleted. A list that is not set is left alone, like every
+    /// other setting, so entries someone else wrote are kept.
+    pub fn numbered_lists(&self) -> Vec<NumberedList> {
+        [
+            ("DenyDeviceIDs", &self.deny_device_ids),
+            ("DenyDeviceClasses", &self.deny_device_classes),
+        ]
+        .into_iter()
+        .filter(|(_, list)| !list.is_empty())
+        .map(|(name, list)| NumberedList {
+            key: format!("{}\\{}", DEVICE_RESTRICTIONS_KEY, name),
+            len: list.len(),
+        })
+        .collect()
+    }
+}
+
+/// A registry key holding a list as the numbered values `1`, `2`, ...
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberedList {
+    pub key: String,
+    pub len: usize,
+}
+
+/// `{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}` with hex digits.
+fn is_class_guid(s: &str) -> bool {
+    let Some(inner) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) else {
+        return false;
+    };
+    let groups: Vec<&str> = inner.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Windows Update policy; fields that are not set are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdatePolicy {
+    /// `NoAutoUpdate`: turn Automatic Updates off.
+    pub no_auto_update: Option<bool>,
+    /// `AUOptions`.
+    pub au_options: Option<AuOption>,
+    /// `NoAutoRebootWithLoggedOnUsers`: never restart while a user is signed in.
+    pub no_auto_reboot_with_logged_on_users: Option<bool>,
+    /// `ScheduledInstallDay`, requires `au_options = "auto-download-schedule-install"`.
+    pub scheduled_install_day: Option<InstallDay>,
+    /// `ScheduledInstallTime`, hour of the day (0-23), same requirement as the day.
+    pub scheduled_install_time: Option<u32>,
+    /// Feature update release to stay on.
+    pub target_release: Option<TargetRelease>,
+}
+
+impl UpdatePolicy {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(hour) = self.scheduled_install_time.filter(|hour| *hour > 23) {
+            return Err(anyhow!(
+                "update_policy: scheduled_install_time {} is not an hour (0-23)",
+                hour
+            ));
+        }
+        let scheduled =
+            self.scheduled_install_day.is_some() || self.scheduled_install_time.is_some();
+        if scheduled && self.au_options != Some(AuOption::AutoDownloadScheduleInstall) {
+            return Err(anyhow!(
+                "update_policy: scheduled_install_day/time require au_options = \"auto-download-schedule-install\""
+            ));
+        }
+        Ok(())
+    }
+
+    /// The registry values the policy enforces, in a stable order.
+    pub fn registry_values(&self) -> Vec<RegistryValue> {
+        let mut values = vec![];
+        let mut push = |key: &str, name: &str, data: Option<RegistryData>| {
+            if let Some(data) = data {
+                values.push(RegistryValue {
+                    key: key.to_owned(),
+                    name: name.to_owned(),
+                    value: data,
+                });
+            }
+        };
+        let dword = |data: Option<u32>| data.map(RegistryData::Dword);
+        push(
+            AU_KEY,
+            "NoAutoUpdate",
+            dword(self.no_auto_update.map(u32::from)),
+        );
+        push(
+            AU_KEY,
+            "AUOptions",
+            dword(self.au_options.map(AuOption::to_raw)),
+        );
+        push(
+            AU_KEY,
+            "NoAutoRebootWithLoggedOnUsers",
+            dword(self.no_auto_reboot_with_logged_on_users.map(u32::from)),
+        );
+        push(
+            AU_KEY,
+            "ScheduledInstallDay",
+            dword(self.scheduled_install_day.map(InstallDay::to_raw)),
+        );
+        push(
+            AU_KEY,
+            "ScheduledInstallTime",
+            dword(self.scheduled_install_time),
+        );
+        if let Some(target) = &self.target_release {
+            push(WU_KEY, "TargetReleaseVersion", Some(RegistryData::Dword(1)));
+            push(
+                WU_KEY,
+                "ProductVersion",
+                Some(RegistryData::String(target.product.name().to_owned())),
+            );
+            push(
+                WU_KEY,
+                "TargetReleaseVersionInfo",
+                Some(RegistryData::String(target.version.to_string())),
+            );
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MemoryHive;
+
+    fn version(s: &str) -> ReleaseVersion {
+        s.parse().unwrap()
+    }
+
+    /// `(key, name, data)` of each value, for comparing against literals.
+    fn entries(values: Vec<RegistryValue>) -> Vec<(String, String, RegistryData)> {
+        values
+            .into_iter()
+            .map(|v| (v.key, v.name, v.value))
+            .collect()
+    }
+
+    #[test]
+    fn raw_values() {
+        let options = [
+            (AuOption::NotifyDownload, 2),
+            (AuOption::AutoDownloadNotifyInstall, 3),
+            (AuOption::AutoDownloadScheduleInstall, 4),
+            (AuOption::LocalAdminChooses, 5),
+            (AuOption::NotifyInstallNotifyRestart, 7),
+        ];
+        for (option, raw) in options {
+            assert_eq!(option.to_raw(), raw, "{:?}", option);
+        }
+        let days = [
+            InstallDay::EveryDay,
+            InstallDay::Sunday,
+            InstallDay::Monday,
+            InstallDay::Tuesday,
+            InstallDay::Wednesday,
+            InstallDay::Thursday,
+            InstallDay::Friday,
+            InstallDay::Saturday,
+        ];
+        for (raw, day) in (0..).zip(days) {
+            assert_eq!(day.to_raw(), raw, "{:?}", day);
+        }
+    }
+
+    #[test]
+    fn update_policy_writes_only_the_set_fields() {
+        assert!(UpdatePolicy::default().registry_values().is_empty());
+
+        let policy = UpdatePolicy {
+            no_auto_update: Some(false),
+            au_options: Some(AuOption::AutoDownloadScheduleInstall),
+            no_auto_reboot_with_logged_on_users: Some(true),
+            scheduled_install_day: Some(InstallDay::Wednesday),
+            scheduled_install_time: Some(3),
+            target_release: None,
+        };
+        policy.validate().unwrap();
+        let au = |name: &str, data: u32| {
+            (
+                AU_KEY.to_owned(),
+                name.to_owned(),
+                RegistryData::Dword(data),
+            )
+        };
+        assert_eq!(
+            entries(policy.registry_values()),
+            vec![
+                au("NoAutoUpdate", 0),
+                au("AUOptions", 4),
+                au("NoAutoRebootWithLoggedOnUsers", 1),
+                au("ScheduledInstallDay", 4),
+                au("ScheduledInstallTime", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn update_policy_rejects_a_schedule_without_scheduled_installs() {
+        let policy = UpdatePolicy {
+            au_options: Some(AuOption::NotifyDownload),
+            scheduled_install_day: Some(InstallDay::Monday),
+            ..UpdatePolicy::default()
+        };
+        assert!(policy.validate().is_err());
+        let policy = UpdatePolicy {
+            au_options: Some(AuOption::AutoDownloadScheduleInstall),
+            scheduled_install_time: Some(24),
+            ..UpdatePolicy::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn release_version_accepts_both_forms() {
+        for s in ["22H2", "21H1", "1909", "2004", "1507", "1812"] {
+            assert_eq!(
+                ReleaseVersion::try_from(s.to_owned()).unwrap().to_string(),
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn release_version_rejects_bad_strings() {
+        for s in [
+            "", "22", "22H", "22H3", "22H0", "22h2", "2200", "2213", "19091", "x2H2", "2xH2",
+            " 22H2", "22H2 ", "+1H2", "abcd",
+        ] {
+            let err = ReleaseVersion::try_from(s.to_owned()).unwrap_err();
+            assert!(err.contains("invalid release version"), "{:?}: {}", s, err);
+        }
+    }
+
+    #[test]
+    fn release_version_orders_by_half_year() {
+        assert!(version("22H2").is_newer_than(&version("22H1")));
+        assert!(version("2004").is_newer_than(&version("1909")));
+        assert!(version("20H2").is_newer_than(&version("2004")));
+        // The same release in both forms.
+        assert!(!version("2004").is_newer_than(&version("20H1")));
+        assert!(!version("20H1").is_newer_than(&version("2004")));
+        assert!(!version("1809").is_newer_than(&version("19H1")));
+    }
+
+    #[test]
+    fn is_behind_compares_product_then_version() {
+        let pin = TargetRelease {
+            product: Product::Windows10,
+            version: version("21H2"),
+        };
+        assert!(!pin.is_behind(Product::Windows10, &version("21H2")));
+        assert!(!pin.is_behind(Product::Windows10, &version("2004")));
+        assert!(pin.is_behind(Product::Windows10, &version("22H2")));
+        // Any Windows 11 release is past a Windows 10 pin.
+        assert!(pin.is_behind(Product::Windows11, &version("21H2")));
+
+        let pin = TargetRelease {
+            product: Product::Windows11,
+            version: version("23H2"),
+        };
+        assert!(!pin.is_behind(Product::Windows10, &version("22H2")));
+        assert!(pin.is_behind(Product::Windows11, &version("24H2")));
+    }
+
+    #[test]
+    fn product_from_build() {
+        assert_eq!(Product::from_build(19045), Product::Windows10);
+        assert_eq!(Product::from_build(22000), Product::Windows11);
+        assert_eq!(Product::from_build(26100), Product::Windows11);
+    }
+
+    #[test]
+    fn running_release_prefers_display_version() {
+        let hive = MemoryHive::new();
+        assert!(running_release(&hive).unwrap().is_none());
+
+        let set = |name: &str, data: &str| {
+            hive.insert(
+                Hive::LocalMachine,
+                CURRENT_VERSION_KEY,
+                name,
+                RegistryData::String(data.to_owned()),
+            )
+        };
+        set("CurrentBuild", "19041");
+        set("ReleaseId", "2004");
+        assert_eq!(
+            running_release(&hive).unwrap(),
+            Some((Product::Windows10, version("2004")))
+        );
+        set("CurrentBuild", "22631");
+        set("DisplayVersion", "23H2");
+        assert_eq!(
+            running_release(&hive).unwrap(),
+            Some((Product::Windows11, version("23H2")))
+        );
+    }
+
+    #[test]
+    fn target_release_values() {
+        let policy = UpdatePolicy {
+            target_release: Some(TargetRelease {
+                product: Product::Windows11,
+                version: version("23H2"),
+            }),
+            ..UpdatePolicy::default()
+        };
+        let wu = |name: &str, data: RegistryData| (WU_KEY.to_owned(), name.to_owned(), data);
+        assert_eq!(
+            entries(policy.registry_values()),
+            vec![
+                wu("TargetReleaseVersion", RegistryData::Dword(1)),
+                wu(
+                    "ProductVersion",
+                    RegistryData::String("Windows 11".to_owned())
+                ),
+                wu(
+                    "TargetReleaseVersionInfo",
+                    RegistryData::String("23H2".to_owned())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn defer_updates_values() {
+        let defer = DeferUpdates {
+            quality_days: 14,
+            feature_days: 180,
+        };
+        let wu = |name: &str, data: u32| {
+            (
+                WU_KEY.to_owned(),
+                name.to_owned(),
+                RegistryData::Dword(data),
+            )
+        };
+        assert_eq!(
+            entries(defer.registry_values()),
+            vec![
+                wu("DeferQualityUpdates", 1),
+                wu("DeferQualityUpdatesPeriodInDays", 14),
+                wu("DeferFeatureUpdates", 1),
+                wu("DeferFeatureUpdatesPeriodInDays", 180),
+            ]
+        );
+    }
+
+    #[test]
+    fn driver_policy_numbers_the_deny_lists() {
+        assert!(DriverPolicy::default().registry_values().is_empty());
+
+        let class = "{4d36e968-e325-11ce-bfc1-08002be10318}";
+        let drivers = DriverPolicy {
+            exclude_from_windows_update: Some(true),
+            deny_device_ids: vec![],
+            deny_device_classes: vec![class.to_owned()],
+        };
+        drivers.validate().unwrap();
+        let classes = format!("{}\\DenyDeviceClasses", DEVICE_RESTRICTIONS_KEY);
+        assert_eq!(
+            entries(drivers.registry_values()),
+            vec![
+                (
+                    WU_KEY.to_owned(),
+                    "ExcludeWUDriversInQualityUpdate".to_owned(),
+                    RegistryData::Dword(1)
+                ),
+                (
+                    DEVICE_RESTRICTIONS_KEY.to_owned(),
+                    "DenyDeviceClasses".to_owned(),
+                    RegistryData::Dword(1)
+                ),
+                (
+                    classes.clone(),
+                    "1".to_owned(),
+                    RegistryData::String(class.to_owned())
+                ),
+            ]
+        );
+        // An empty list is left alone, the class list keeps one entry.
+        assert_eq!(
+            drivers.numbered_lists(),
+            vec![NumberedList {
+                key: classes,
+                len: 1
+            }]
+        );
+    }
+}